@@ -0,0 +1,388 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use crate::config::{Difficulty, GameConfig};
+use crate::direction::{Direction, Turn};
+use crate::game::GameState;
+use crate::hexagon::HexCoordinates;
+use crate::input::InputQueue;
+use crate::snake::Snake;
+
+// anything that can steer a snake, asked once per tick before the snakes move
+pub trait Controller {
+    fn next_turns(&mut self, game: &GameState, player: usize) -> Vec<Turn>;
+}
+
+// Heads for the apple along the shortest path over land it can find within its lookahead, going around
+// every snake's body. When the apple is too far or walled off it goes where there's the most room.
+pub struct Rival {
+    lookahead: usize,
+    // only takes a path when there's room enough for the whole snake after it, and keeps away from other heads
+    careful: bool,
+}
+
+impl Rival {
+    pub fn new(difficulty: Difficulty) -> Rival {
+        match difficulty {
+            Difficulty::Easy => Rival { lookahead: 2, careful: false },
+            Difficulty::Normal => Rival { lookahead: 8, careful: false },
+            Difficulty::Hard => Rival { lookahead: usize::MAX, careful: true },
+        }
+    }
+
+    pub fn get_lookahead(&self) -> usize {
+        self.lookahead
+    }
+}
+
+impl Controller for Rival {
+    fn next_turns(&mut self, game: &GameState, player: usize) -> Vec<Turn> {
+        if !game.is_alive(player) {
+            return Vec::new();
+        }
+        let snake = game.get_player_snake(player);
+        let free = |coord: &HexCoordinates| {
+            matches!(game.get_board().get(coord), Some(tile) if !tile.is_hole())
+                && !game.get_snakes().any(|other| other.check_collision(coord))
+        };
+        let options: Vec<(Option<Turn>, HexCoordinates)> = ahead(&(snake.get_head().clone(), snake.get_dir())).into_iter()
+            .map(|(turn, (next, _))| (turn, next))
+            .filter(|(_, next)| free(next))
+            .collect();
+        // another head could get to the same tile at the same time
+        let contested = |coord: &HexCoordinates| (0..game.get_players())
+            .any(|other| other != player && game.is_alive(other) && game.get_player_snake(other).get_head().distance(coord) == 1);
+        let options = match options.iter().filter(|(_, next)| !contested(next)).cloned().collect::<Vec<_>>() {
+            uncontested if self.careful && !uncontested.is_empty() => uncontested,
+            _ => options
+        };
+
+        let to_apple = distances(game.get_apple(), self.lookahead, &free);
+        let towards_apple = options.iter()
+            .filter(|(_, next)| to_apple.contains_key(next))
+            .filter(|(_, next)| !self.careful || distances(next, usize::MAX, &free).len() >= snake.len())
+            .min_by_key(|(_, next)| to_apple[next]);
+        let most_room = || options.iter().max_by_key(|(_, next)| distances(next, self.lookahead, &free).len());
+        // with nowhere to go it might as well go straight on
+        match towards_apple.or_else(most_room) {
+            Some((Some(turn), _)) => vec![*turn],
+            _ => Vec::new()
+        }
+    }
+}
+
+// Plays as well as it can: takes the shortest path to the apple when it can still get to its own tail once
+// it has eaten, otherwise chases its tail, the long way round, until the way to the apple is safe again.
+pub struct Autopilot;
+
+impl Autopilot {
+    pub fn new() -> Autopilot {
+        Autopilot
+    }
+}
+
+impl Default for Autopilot {
+    fn default() -> Autopilot {
+        Autopilot::new()
+    }
+}
+
+impl Controller for Autopilot {
+    fn next_turns(&mut self, game: &GameState, player: usize) -> Vec<Turn> {
+        if !game.is_alive(player) {
+            return Vec::new();
+        }
+        let snake = game.get_player_snake(player);
+        let blocked = blocked_tiles(game);
+        let free = |coord: &HexCoordinates| {
+            matches!(game.get_board().get(coord), Some(tile) if !tile.is_hole()) && !blocked.contains(coord)
+        };
+        let options: Vec<(Option<Turn>, Heading)> = ahead(&(snake.get_head().clone(), snake.get_dir())).into_iter()
+            .filter(|(_, (next, _))| free(next))
+            .collect();
+
+        let to_apple = options.iter()
+            .filter_map(|(turn, next)| Some((turn, shortest_path(next, game.get_apple(), &free)?)))
+            .filter(|(_, path)| can_reach_tail_after(snake, path, &free))
+            .min_by_key(|(_, path)| path.len())
+            .map(|(turn, _)| *turn);
+        let turn = to_apple.or_else(|| {
+            // the farther the tail, the more time there is for the way to the apple to open up
+            let tail = snake.get_end();
+            let reach = |coord: &HexCoordinates| free(coord) || coord == tail;
+            let to_tail = |next: &Heading| moves(next, &reach).get(tail).copied();
+            options.iter().filter(|(_, next)| to_tail(next).is_some()).max_by_key(|(_, next)| to_tail(next))
+                .or_else(|| options.iter().max_by_key(|(_, next)| moves(next, &free).len()))
+                .map(|(turn, _)| *turn)
+        });
+        // with nowhere to go it might as well go straight on
+        turn.flatten().into_iter().collect()
+    }
+}
+
+// where the head is and where it's looking
+type Heading = (HexCoordinates, Direction);
+
+// the three moves a head can make, straight on first
+fn ahead((coord, dir): &Heading) -> [(Option<Turn>, Heading); 3] {
+    [None, Some(Turn::Left), Some(Turn::Right)].map(|turn| {
+        let dir = turn.map_or(*dir, |turn| Direction::change_dir(*dir, turn));
+        (turn, (coord.move_in_dir(dir), dir))
+    })
+}
+
+// every snake's body but the ends that move out of the way on the next tick
+fn blocked_tiles(game: &GameState) -> HashSet<HexCoordinates> {
+    let mut blocked = HashSet::new();
+    for (player, snake) in game.get_snakes().enumerate() {
+        blocked.extend(snake.body().map(|part| part.coordinates.clone()));
+        if game.is_alive(player) && !has_just_grown(snake) {
+            blocked.remove(snake.get_end());
+        }
+    }
+    blocked
+}
+
+// a snake grows by doubling its end, which then stays put for a tick
+fn has_just_grown(snake: &Snake) -> bool {
+    snake.len() > 1 && snake.body().nth(snake.len() - 2).is_some_and(|before| &before.coordinates == snake.get_end())
+}
+
+// whether after following the path and eating at its end the snake still has a way to its own tail,
+// so it can keep going round for as long as it needs
+fn can_reach_tail_after(snake: &Snake, path: &[Heading], free: &dyn Fn(&HexCoordinates) -> bool) -> bool {
+    let mut body: VecDeque<HexCoordinates> = snake.body().map(|part| part.coordinates.clone()).collect();
+    for (step, _) in path {
+        body.push_front(step.clone());
+        body.pop_back();
+    }
+    // the apple makes it one longer
+    let end = body.back().unwrap().clone();
+    body.push_back(end.clone());
+    let moved_body: HashSet<&HexCoordinates> = body.iter().collect();
+    // the tiles it has left behind are land again
+    let own_body: HashSet<HexCoordinates> = snake.body().map(|part| part.coordinates.clone()).collect();
+    let reach = |coord: &HexCoordinates| coord == &end || (!moved_body.contains(coord) && (free(coord) || own_body.contains(coord)));
+    moves(path.last().unwrap(), &reach).contains_key(&end)
+}
+
+// A* over the tiles free says can be walked on, both ends included; a head can't turn round on the spot,
+// so it's not enough to know where a path goes through but also which way it goes there
+fn shortest_path(start: &Heading, goal: &HexCoordinates, free: &dyn Fn(&HexCoordinates) -> bool) -> Option<Vec<Heading>> {
+    let mut came_from: HashMap<Heading, Heading> = HashMap::new();
+    let mut cost = HashMap::new();
+    let mut open = BinaryHeap::new();
+    // the heap can't order hexes, so it keeps indexes into this
+    let mut headings = vec![start.clone()];
+    cost.insert(start.clone(), 0);
+    open.push((Reverse(start.0.distance(goal)), Reverse(0), 0));
+    while let Some((_, Reverse(steps), index)) = open.pop() {
+        let heading = headings[index].clone();
+        if &heading.0 == goal {
+            let mut path = vec![heading];
+            while let Some(previous) = came_from.get(path.last().unwrap()) {
+                path.push(previous.clone());
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if cost[&heading] < steps {
+            continue;
+        }
+        for (_, next) in ahead(&heading) {
+            if !free(&next.0) || cost.get(&next).is_some_and(|known| *known <= steps + 1) {
+                continue;
+            }
+            cost.insert(next.clone(), steps + 1);
+            came_from.insert(next.clone(), heading.clone());
+            open.push((Reverse(steps + 1 + next.0.distance(goal)), Reverse(steps + 1), headings.len()));
+            headings.push(next);
+        }
+    }
+    None
+}
+
+// how many moves it takes the head to get to every tile it can, turning only as a snake can
+fn moves(start: &Heading, free: &dyn Fn(&HexCoordinates) -> bool) -> HashMap<HexCoordinates, usize> {
+    let mut moves = HashMap::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(start.clone());
+    queue.push_back((start.clone(), 0));
+    while let Some((heading, count)) = queue.pop_front() {
+        moves.entry(heading.0.clone()).or_insert(count);
+        for (_, next) in ahead(&heading) {
+            if free(&next.0) && seen.insert(next.clone()) {
+                queue.push_back((next, count + 1));
+            }
+        }
+    }
+    moves
+}
+
+// the controllers there are to pick from by name, e.g. on the command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bot {
+    Autopilot,
+    Rival(Difficulty),
+}
+
+impl Bot {
+    pub fn controller(&self) -> Box<dyn Controller> {
+        match self {
+            Bot::Autopilot => Box::new(Autopilot::new()),
+            Bot::Rival(difficulty) => Box::new(Rival::new(*difficulty)),
+        }
+    }
+}
+
+impl FromStr for Bot {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Bot, String> {
+        match text {
+            "autopilot" => Ok(Bot::Autopilot),
+            _ => text.parse().map(Bot::Rival).map_err(|_| format!("unknown bot: {}, expected autopilot, easy, normal or hard", text))
+        }
+    }
+}
+
+impl fmt::Display for Bot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bot::Autopilot => write!(f, "autopilot"),
+            Bot::Rival(difficulty) => write!(f, "{}", difficulty.name()),
+        }
+    }
+}
+
+// the first autopilots players are on autopilot and the others steer by hand, the rivals come last
+pub fn controllers(config: &GameConfig, autopilots: usize) -> Vec<Option<Box<dyn Controller>>> {
+    (0..config.snakes())
+        .map(|snake| -> Option<Box<dyn Controller>> {
+            if snake >= config.players {
+                Some(Bot::Rival(config.rival_difficulty).controller())
+            } else if snake < autopilots {
+                Some(Bot::Autopilot.controller())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// every snake's turns for the tick, from its controller or else from the keys pressed for it
+pub fn next_turns(controllers: &mut [Option<Box<dyn Controller>>], inputs: &mut [InputQueue], game: &GameState) -> Vec<Vec<Turn>> {
+    controllers.iter_mut().enumerate()
+        .map(|(snake, controller)| match controller {
+            Some(controller) => controller.next_turns(game, snake),
+            None => inputs[snake].next_turns()
+        })
+        .collect()
+}
+
+// plays the game with a controller for every snake, in order, until it's over or max_ticks have gone by
+pub fn play_out(game: &mut GameState, controllers: &mut [Box<dyn Controller>], max_ticks: u32) {
+    while !game.is_over() && game.get_tick() < max_ticks {
+        let turns: Vec<Vec<Turn>> = controllers.iter_mut().enumerate()
+            .map(|(player, controller)| controller.next_turns(game, player))
+            .collect();
+        game.step_players(&turns);
+    }
+}
+
+// how many moves it takes to get to every free tile up to the given number of moves from the start
+fn distances(start: &HexCoordinates, limit: usize, free: &dyn Fn(&HexCoordinates) -> bool) -> HashMap<HexCoordinates, usize> {
+    let mut distances = HashMap::new();
+    let mut queue = VecDeque::new();
+    distances.insert(start.clone(), 0);
+    queue.push_back(start.clone());
+    while let Some(coord) = queue.pop_front() {
+        let distance = distances[&coord];
+        if distance >= limit {
+            continue;
+        }
+        for neighbour in coord.neighbors() {
+            if free(&neighbour) && !distances.contains_key(&neighbour) {
+                distances.insert(neighbour.clone(), distance + 1);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::level::Level;
+
+    fn play(rival: &mut Rival, game: &mut GameState, ticks: u32) {
+        for _ in 0..ticks {
+            let turns = rival.next_turns(game, 0);
+            game.step(&turns);
+        }
+    }
+
+    #[test]
+    fn test_goes_for_the_apple() {
+        let config = GameConfig { holes_num: 0, ..GameConfig::default() };
+        let level = Level::parse("direction: East\nmap:\n . . . . . .\n. . S . @ . .\n . . . . . .\n").unwrap();
+        for difficulty in Difficulty::ALL {
+            let mut game = GameState::from_level(&config, &level, 1);
+            let mut rival = Rival::new(difficulty);
+            let apple = game.get_apple().clone();
+            let moves = game.get_snake().get_head().distance(&apple);
+            assert!(rival.get_lookahead() >= moves as usize);
+            play(&mut rival, &mut game, moves as u32);
+            assert_eq!(game.get_score(), 1, "{:?}", difficulty);
+            assert!(!game.is_over());
+        }
+    }
+
+    #[test]
+    fn test_stays_on_land() {
+        for difficulty in Difficulty::ALL {
+            let mut game = GameState::new(&GameConfig::default(), 7);
+            let mut rival = Rival::new(difficulty);
+            play(&mut rival, &mut game, 60);
+            assert!(!game.is_over(), "{:?}", difficulty);
+            assert!(game.get_score() > 0);
+        }
+    }
+
+    #[test]
+    fn test_rivals_share_the_island() {
+        let config = GameConfig { players: 1, rivals: 1, ..GameConfig::default() };
+        let mut game = GameState::new(&config, 3);
+        let mut rivals = [Rival::new(Difficulty::Hard), Rival::new(Difficulty::Normal)];
+        for _ in 0..60 {
+            let turns: Vec<Vec<Turn>> = rivals.iter_mut().enumerate().map(|(player, rival)| rival.next_turns(&game, player)).collect();
+            game.step_players(&turns);
+            assert!(game.get_snakes().all(|snake| !snake.falling));
+        }
+        assert!(!game.is_over());
+        assert!(game.get_scores().iter().all(|score| *score > 0));
+    }
+
+    #[test]
+    fn test_autopilot() {
+        let mut game = GameState::new(&GameConfig::default(), 1);
+        let mut controllers: Vec<Box<dyn Controller>> = vec![Box::new(Autopilot::new())];
+        play_out(&mut game, &mut controllers, 150);
+        assert!(!game.is_over());
+        assert!(game.get_score() >= 10);
+
+        // it doesn't follow the apple into a dead end it can't turn round in
+        let level = Level::parse("direction: East\nmap:\n . . . . . .\n. . S . . . . . . @\n . . . . . .\n").unwrap();
+        let mut game = GameState::from_level(&GameConfig { holes_num: 0, ..GameConfig::default() }, &level, 1);
+        play_out(&mut game, &mut controllers, 50);
+        assert!(!game.is_over());
+        assert_eq!(game.get_score(), 0);
+        let mut game = GameState::from_level(&GameConfig { holes_num: 0, ..GameConfig::default() }, &level, 1);
+        play_out(&mut game, &mut [Box::new(Rival::new(Difficulty::Normal)) as Box<dyn Controller>], 50);
+        assert!(game.is_over());
+    }
+}
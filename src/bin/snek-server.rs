@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use snek::config::GameConfig;
+use snek::protocol::DEFAULT_PORT;
+use snek::server::Server;
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    bind: String,
+    // None to keep hosting games until stopped
+    games: Option<u32>,
+    seed: Option<u64>,
+    config: Option<PathBuf>,
+    overrides: Vec<(String, String)>,
+}
+
+impl Default for Args {
+    fn default() -> Args {
+        Args { bind: format!("0.0.0.0:{}", DEFAULT_PORT), games: None, seed: None, config: None, overrides: Vec::new() }
+    }
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bind" => parsed.bind = args.next().ok_or("--bind expects an address")?,
+                "--port" => {
+                    let value = args.next().ok_or("--port expects a number")?;
+                    let port: u16 = value.parse().map_err(|_| format!("invalid port: {}", value))?;
+                    parsed.bind = format!("0.0.0.0:{}", port);
+                }
+                "--games" => {
+                    let value = args.next().ok_or("--games expects a number")?;
+                    parsed.games = Some(value.parse().map_err(|_| format!("invalid number of games: {}", value))?);
+                }
+                "--seed" => {
+                    let value = args.next().ok_or("--seed expects a value")?;
+                    parsed.seed = Some(value.parse().map_err(|_| format!("invalid seed: {}", value))?);
+                }
+                "--config" => {
+                    let value = args.next().ok_or("--config expects a file")?;
+                    parsed.config = Some(PathBuf::from(value));
+                }
+                "--radius" | "--holes-num" | "--holes-interval" | "--tick-rate" | "--speed-curve" | "--speed-by" | "--level"
+                | "--island" | "--players" | "--rivals" | "--rival-difficulty" => {
+                    let value = args.next().ok_or(format!("{} expects a value", arg))?;
+                    parsed.overrides.push((arg[2..].replace('-', "_"), value));
+                }
+                _ => return Err(format!("unknown argument: {}", arg))
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+// only the settings given on the command line or in --config, like snek-sim
+fn load_config(args: &Args) -> Result<GameConfig, String> {
+    GameConfig::load_with_overrides_from(args.config.as_deref(), &args.overrides)
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let config = load_config(args)?;
+    let (events, received) = mpsc::channel();
+    thread::spawn(move || {
+        for event in received {
+            println!("{}", event);
+        }
+    });
+    let server = Server::bind(&args.bind, &config, events).map_err(|err| format!("could not listen on {}: {}", args.bind, err))?;
+    let addr = server.local_addr().map_err(|err| err.to_string())?;
+    let mut game = 0;
+    while args.games.is_none_or(|games| game < games) {
+        let seed = args.seed.map_or_else(rand::random, |seed| seed.wrapping_add(game as u64));
+        println!("waiting on {} for {} players, seed {}", addr, config.players, seed);
+        match server.play(seed) {
+            Ok(snapshot) => println!("game over after {} ticks, scores {:?}", snapshot.tick, snapshot.scores),
+            Err(err) => eprintln!("game stopped: {}", err)
+        }
+        game += 1;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("usage: snek-server [--port <port>] [--bind <address:port>] [--games <n>] [--seed <first seed>]");
+            eprintln!("                   [--config <file>] [--radius <n>] [--holes-num <n>] [--holes-interval <n>]");
+            eprintln!("                   [--tick-rate <n>] [--speed-curve <curve>] [--speed-by <score|length>] [--level <file>]");
+            eprintln!("                   [--island hexagon|generated] [--players <1-4>] [--rivals <n>]");
+            eprintln!("                   [--rival-difficulty <easy|normal|hard>]");
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args = |list: &[&str]| Args::parse(list.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&["snek-server"]), Ok(Args::default()));
+        let parsed = args(&["snek-server", "--port", "9000", "--players", "2", "--games", "3"]).unwrap();
+        assert_eq!(parsed.bind, "0.0.0.0:9000");
+        assert_eq!(parsed.games, Some(3));
+        assert_eq!(parsed.overrides, vec![("players".to_string(), "2".to_string())]);
+        assert_eq!(args(&["snek-server", "--bind", "127.0.0.1:7000"]).unwrap().bind, "127.0.0.1:7000");
+        assert!(args(&["snek-server", "--port", "99999"]).is_err());
+        assert!(args(&["snek-server", "--hex-side", "20"]).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+use serde::Serialize;
+use snek::ai::Bot;
+use snek::config::GameConfig;
+use snek::sim::{self, GameReport, Summary};
+
+const DEFAULT_GAMES: u32 = 100;
+const DEFAULT_MAX_TICKS: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    games: u32,
+    bot: Bot,
+    seed: Option<u64>,
+    config: Option<PathBuf>,
+    overrides: Vec<(String, String)>,
+    max_ticks: u32,
+    format: Format,
+    summary_only: bool,
+}
+
+impl Default for Args {
+    fn default() -> Args {
+        Args { games: DEFAULT_GAMES, bot: Bot::Autopilot, seed: None, config: None, overrides: Vec::new(), max_ticks: DEFAULT_MAX_TICKS,
+            format: Format::Csv, summary_only: false }
+    }
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--games" => {
+                    let value = args.next().ok_or("--games expects a number")?;
+                    parsed.games = value.parse().map_err(|_| format!("invalid number of games: {}", value))?;
+                }
+                "--bot" => {
+                    let value = args.next().ok_or("--bot expects autopilot, easy, normal or hard")?;
+                    parsed.bot = value.parse()?;
+                }
+                "--seed" => {
+                    let value = args.next().ok_or("--seed expects a value")?;
+                    parsed.seed = Some(value.parse().map_err(|_| format!("invalid seed: {}", value))?);
+                }
+                "--config" => {
+                    let value = args.next().ok_or("--config expects a file")?;
+                    parsed.config = Some(PathBuf::from(value));
+                }
+                "--max-ticks" => {
+                    let value = args.next().ok_or("--max-ticks expects a number")?;
+                    parsed.max_ticks = value.parse().map_err(|_| format!("invalid number of ticks: {}", value))?;
+                }
+                "--format" => {
+                    parsed.format = match args.next().as_deref() {
+                        Some("csv") => Format::Csv,
+                        Some("json") => Format::Json,
+                        _ => return Err("--format expects csv or json".to_string())
+                    };
+                }
+                "--summary" => parsed.summary_only = true,
+                "--radius" | "--holes-num" | "--holes-interval" | "--level" | "--island" | "--players" | "--rivals"
+                | "--rival-difficulty" => {
+                    let value = args.next().ok_or(format!("{} expects a value", arg))?;
+                    parsed.overrides.push((arg[2..].replace('-', "_"), value));
+                }
+                _ => return Err(format!("unknown argument: {}", arg))
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    bot: String,
+    config: &'a GameConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    games: Option<&'a [GameReport]>,
+    summary: Summary,
+}
+
+// only the settings given on the command line or in --config, the player's own config.toml is left out
+fn load_config(args: &Args) -> Result<GameConfig, String> {
+    GameConfig::load_with_overrides_from(args.config.as_deref(), &args.overrides)
+}
+
+fn run(args: &Args) -> Result<String, String> {
+    let config = load_config(args)?;
+    let mut reports = Vec::new();
+    for game in 0..args.games {
+        let seed = args.seed.map_or_else(rand::random, |seed| seed.wrapping_add(game as u64));
+        reports.push(sim::simulate(&config, seed, args.bot, args.max_ticks).map_err(|err| format!("seed {}: {}", seed, err))?);
+    }
+    let summary = Summary::new(&reports);
+    let output = match args.format {
+        Format::Csv if args.summary_only => format!("{}\n{}\n", Summary::CSV_HEADER, summary.csv_row()),
+        Format::Csv => {
+            let rows: Vec<String> = reports.iter().map(|report| report.csv_row()).collect();
+            format!("{}\n{}\n", GameReport::CSV_HEADER, rows.join("\n"))
+        }
+        Format::Json => {
+            let games = if args.summary_only { None } else { Some(&reports[..]) };
+            let output = JsonOutput { bot: args.bot.to_string(), config: &config, games, summary };
+            serde_json::to_string_pretty(&output).map_err(|err| err.to_string())? + "\n"
+        }
+    };
+    Ok(output)
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("usage: snek-sim [--games <n>] [--bot autopilot|easy|normal|hard] [--seed <first seed>] [--max-ticks <n>]");
+            eprintln!("                [--format csv|json] [--summary] [--config <file>] [--radius <n>] [--holes-num <n>]");
+            eprintln!("                [--holes-interval <n>] [--level <file>] [--island hexagon|generated] [--players <n>]");
+            eprintln!("                [--rivals <n>] [--rival-difficulty <easy|normal|hard>]");
+            std::process::exit(2);
+        }
+    };
+    match run(&args) {
+        Ok(output) => print!("{}", output),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snek::config::Difficulty;
+
+    #[test]
+    fn test_parse_args() {
+        let args = |list: &[&str]| Args::parse(list.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&["snek-sim"]), Ok(Args::default()));
+        let parsed = args(&["snek-sim", "--games", "5", "--bot", "easy", "--holes-num", "4", "--format", "json", "--summary"]).unwrap();
+        assert_eq!(parsed.games, 5);
+        assert_eq!(parsed.bot, Bot::Rival(Difficulty::Easy));
+        assert_eq!(parsed.overrides, vec![("holes_num".to_string(), "4".to_string())]);
+        assert_eq!(parsed.format, Format::Json);
+        assert!(parsed.summary_only);
+        assert!(args(&["snek-sim", "--format", "xml"]).is_err());
+        assert!(args(&["snek-sim", "--bot", "genius"]).is_err());
+        assert!(args(&["snek-sim", "--tick-rate", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let args = Args { games: 3, bot: Bot::Rival(Difficulty::Easy), seed: Some(10), max_ticks: 300,
+            overrides: vec![("radius".to_string(), "5".to_string())], ..Args::default() };
+        let csv = run(&args).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().nth(1).unwrap().starts_with("10,"));
+        let json: serde_json::Value = serde_json::from_str(&run(&Args { format: Format::Json, ..args }).unwrap()).unwrap();
+        assert_eq!(json["games"].as_array().unwrap().len(), 3);
+        assert_eq!(json["summary"]["games"], 3);
+        assert_eq!(json["config"]["radius"], 5);
+    }
+}
@@ -0,0 +1,510 @@
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use snek::ai::{self, Controller};
+use snek::config::{ControlScheme, Difficulty, GameConfig};
+use snek::direction::{Direction, Turn};
+use snek::game::GameState;
+use snek::hexagon::HexCoordinates;
+use snek::highscore::{self, HighScores};
+use snek::input::InputQueue;
+
+// the window's colours as near as the 256 ANSI colours get
+const PLAYER_COLORS: [u8; 4] = [28, 26, 172, 127];
+const DEAD_COLOR: u8 = 242;
+const LAND_COLOR: u8 = 178;
+const APPLE_COLOR: u8 = 196;
+
+const CTRL_C: u8 = 3;
+const ESCAPE: u8 = 27;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Left,
+    Right,
+    // Ctrl-C; escape doesn't quit, it may be the start of an arrow the terminal sends in pieces
+    Quit,
+}
+
+// what the board is drawn with, Unicode unless the terminal can't take it
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Glyphs {
+    land: char,
+    apple: char,
+    head: char,
+    body: char,
+}
+
+const UNICODE: Glyphs = Glyphs { land: '·', apple: '●', head: '◉', body: '○' };
+const ASCII: Glyphs = Glyphs { land: '.', apple: '@', head: 'O', body: 'o' };
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    seed: Option<u64>,
+    name: Option<String>,
+    config: Option<PathBuf>,
+    overrides: Vec<(String, String)>,
+    difficulty: Option<Difficulty>,
+    ascii: bool,
+    color: bool,
+}
+
+impl Default for Args {
+    fn default() -> Args {
+        Args { seed: None, name: None, config: None, overrides: Vec::new(), difficulty: None, ascii: false, color: true }
+    }
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().ok_or("--seed expects a value")?;
+                    parsed.seed = Some(value.parse().map_err(|_| format!("invalid seed: {}", value))?);
+                }
+                "--name" => parsed.name = Some(args.next().ok_or("--name expects a name")?),
+                "--config" => {
+                    let value = args.next().ok_or("--config expects a file")?;
+                    parsed.config = Some(PathBuf::from(value));
+                }
+                "--difficulty" => {
+                    let value = args.next().ok_or("--difficulty expects easy, normal or hard")?;
+                    parsed.difficulty = Some(value.parse()?);
+                }
+                "--ascii" => parsed.ascii = true,
+                "--no-color" => parsed.color = false,
+                "--radius" | "--holes-num" | "--holes-interval" | "--tick-rate" | "--speed-curve" | "--speed-by" | "--level"
+                | "--island" | "--controls" | "--players" | "--rivals" | "--rival-difficulty" => {
+                    let value = args.next().ok_or(format!("{} expects a value", arg))?;
+                    parsed.overrides.push((arg[2..].replace('-', "_"), value));
+                }
+                _ => return Err(format!("unknown argument: {}", arg))
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+// the same config.toml as the window, so both play by the same rules
+fn load_config(args: &Args) -> Result<GameConfig, String> {
+    let mut config = GameConfig::load_with_overrides(args.config.as_deref(), &args.overrides)?;
+    if let Some(difficulty) = args.difficulty {
+        difficulty.apply(&mut config);
+    }
+    // two players share the keyboard at most
+    if config.players > 2 {
+        return Err("the terminal only has keys for 2 players".to_string());
+    }
+    Ok(config)
+}
+
+// An arrow comes as ESC [ C or ESC [ D. What's left at the end that could still be the start of a sequence stays
+// in pending for the next read to finish, the rest is taken out.
+fn parse_keys(pending: &mut Vec<u8>) -> Vec<Key> {
+    let bytes = &pending[..];
+    let mut keys = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        match &bytes[index..] {
+            [ESCAPE, b'[', b'D', ..] | [ESCAPE, b'O', b'D', ..] => {
+                keys.push(Key::Left);
+                index += 3;
+            }
+            [ESCAPE, b'[', b'C', ..] | [ESCAPE, b'O', b'C', ..] => {
+                keys.push(Key::Right);
+                index += 3;
+            }
+            // the other escape sequences aren't used, so they're skipped whole
+            [ESCAPE, b'[', rest @ ..] => match rest.iter().position(|byte| byte.is_ascii_alphabetic() || *byte == b'~') {
+                Some(end) => index += end + 3,
+                None => break
+            },
+            [ESCAPE, b'O', _, ..] => index += 3,
+            [ESCAPE] | [ESCAPE, b'O'] => break,
+            // alt and a key, or escape pressed on its own
+            [ESCAPE, ..] => index += 1,
+            [CTRL_C, ..] => {
+                keys.push(Key::Quit);
+                index += 1;
+            }
+            [byte, ..] => {
+                keys.push(Key::Char(byte.to_ascii_lowercase() as char));
+                index += 1;
+            }
+            [] => break
+        }
+    }
+    pending.drain(..index);
+    keys
+}
+
+// laid out like the window's default keys: the second player's around J instead of S
+fn key_turn(key: Key, controls: ControlScheme) -> Option<(usize, Turn)> {
+    let absolute = |player, dir| Some((player, Turn::Towards(dir)));
+    match (controls, key) {
+        (ControlScheme::Relative, Key::Left | Key::Char('a')) => Some((0, Turn::Left)),
+        (ControlScheme::Relative, Key::Right | Key::Char('d')) => Some((0, Turn::Right)),
+        (ControlScheme::Relative, Key::Char('j')) => Some((1, Turn::Left)),
+        (ControlScheme::Relative, Key::Char('l')) => Some((1, Turn::Right)),
+        (ControlScheme::Absolute, Key::Char(key)) => match key {
+            'w' => absolute(0, Direction::NorthWest),
+            'e' => absolute(0, Direction::NorthEast),
+            'a' => absolute(0, Direction::West),
+            'd' => absolute(0, Direction::East),
+            'z' => absolute(0, Direction::SouthWest),
+            'x' => absolute(0, Direction::SouthEast),
+            'u' => absolute(1, Direction::NorthWest),
+            'i' => absolute(1, Direction::NorthEast),
+            'h' => absolute(1, Direction::West),
+            'k' => absolute(1, Direction::East),
+            'n' => absolute(1, Direction::SouthWest),
+            'm' => absolute(1, Direction::SouthEast),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn paint(text: &str, color: Option<u8>) -> String {
+    match color {
+        Some(color) => format!("\x1b[38;5;{}m{}\x1b[0m", color, text),
+        None => text.to_string()
+    }
+}
+
+// The island in offset rows, the same way the level files draw it: a tile is a character and a space, and
+// every row is shifted half a tile from the ones next to it. The holes are left empty, like the void around.
+fn draw_board(game: &GameState, glyphs: Glyphs, color: bool) -> Vec<String> {
+    // a hex's column in half tiles and its row, from where the window draws it
+    let position = |r: i32, b: i32| (2 * r + b, -b);
+    let positions = game.get_board().keys().map(|coord| position(coord.get_r(), coord.get_b()));
+    let (min_x, min_y) = positions.clone().fold((i32::MAX, i32::MAX), |(x, y), (px, py)| (x.min(px), y.min(py)));
+    let (max_x, max_y) = positions.fold((i32::MIN, i32::MIN), |(x, y), (px, py)| (x.max(px), y.max(py)));
+    let mut cells = vec![vec![(' ', None); (max_x - min_x + 1) as usize]; (max_y - min_y + 1) as usize];
+    let mut put = |coord: &HexCoordinates, glyph, cell_color: u8| {
+        let (x, y) = position(coord.get_r(), coord.get_b());
+        cells[(y - min_y) as usize][(x - min_x) as usize] = (glyph, Some(cell_color).filter(|_| color));
+    };
+
+    for (coord, tile) in game.get_board() {
+        if !tile.is_hole() {
+            put(coord, glyphs.land, LAND_COLOR);
+        }
+    }
+    put(game.get_apple(), glyphs.apple, APPLE_COLOR);
+    for (player, snake) in game.get_snakes().enumerate() {
+        let dead = !game.is_alive(player) && !snake.falling && game.get_players() > 1;
+        let snake_color = if dead { DEAD_COLOR } else { PLAYER_COLORS[player] };
+        // the head last, so it's on top where a snake crosses itself
+        for part in snake.body().skip(1) {
+            put(&part.coordinates, glyphs.body, snake_color);
+        }
+        if !snake.is_empty() {
+            put(snake.get_head(), glyphs.head, snake_color);
+        }
+    }
+
+    cells.iter().map(|row| {
+        let line: String = row.iter().map(|(glyph, cell_color)| paint(&glyph.to_string(), *cell_color)).collect();
+        line.trim_end().to_string()
+    }).collect()
+}
+
+// puts the terminal in raw mode on the alternate screen, and back the way it was when dropped
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["raw", "-echo"])?;
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Terminal { saved })
+    }
+
+    fn draw(&self, lines: &[String]) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        write!(out, "\x1b[H")?;
+        for line in lines {
+            write!(out, "{}\x1b[K\r\n", line)?;
+        }
+        write!(out, "\x1b[J")?;
+        out.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+// stty works on the terminal it's given as stdin, which is ours
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).stderr(Stdio::null()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stdin is not a terminal"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn new_game(config: &GameConfig, seed: Option<u64>) -> Result<GameState, String> {
+    let seed = seed.unwrap_or_else(rand::random);
+    let level = config.load_level(seed).map_err(|err| err.to_string())?;
+    Ok(GameState::from_level(config, &level, seed))
+}
+
+struct Game {
+    config: GameConfig,
+    seed: Option<u64>,
+    name: String,
+    glyphs: Glyphs,
+    color: bool,
+    game: GameState,
+    inputs: Vec<InputQueue>,
+    // the rivals', the players all steer by hand
+    controllers: Vec<Option<Box<dyn Controller>>>,
+    paused: bool,
+    play_time: Duration,
+    highscores: HighScores,
+    highscore_place: Option<usize>,
+}
+
+impl Game {
+    fn new(config: GameConfig, args: &Args) -> Result<Game, String> {
+        let highscores = HighScores::load_default().unwrap_or_else(|err| {
+            eprintln!("could not load high scores: {}", err);
+            HighScores::default()
+        });
+        let name = args.name.clone().unwrap_or_else(highscore::default_name);
+        let mut game = Game {
+            game: new_game(&config, args.seed)?,
+            inputs: Vec::new(),
+            controllers: Vec::new(),
+            config,
+            seed: args.seed,
+            name,
+            glyphs: if args.ascii { ASCII } else { UNICODE },
+            color: args.color,
+            paused: false,
+            play_time: Duration::ZERO,
+            highscores,
+            highscore_place: None,
+        };
+        game.reset();
+        Ok(game)
+    }
+
+    fn restart(&mut self) -> Result<(), String> {
+        self.game = new_game(&self.config, self.seed)?;
+        self.reset();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.inputs = vec![InputQueue::new(); self.config.players];
+        self.controllers = ai::controllers(&self.config, 0);
+        self.paused = false;
+        self.play_time = Duration::ZERO;
+        self.highscore_place = None;
+    }
+
+    // false once the player wants to leave
+    fn on_key(&mut self, key: Key) -> Result<bool, String> {
+        match key {
+            Key::Quit | Key::Char('q') => return Ok(false),
+            Key::Char('p') if !self.game.is_over() => self.paused = !self.paused,
+            Key::Char('r') if self.game.is_over() => self.restart()?,
+            _ => {
+                if let Some((player, turn)) = key_turn(key, self.config.controls).filter(|(player, _)| *player < self.inputs.len()) {
+                    if !self.paused {
+                        self.inputs[player].push(turn);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn tick(&mut self) {
+        let was_over = self.game.is_over();
+        let turns = ai::next_turns(&mut self.controllers, &mut self.inputs, &self.game);
+        self.game.step_players(&turns);
+        if self.game.is_over() && !was_over && self.game.get_players() == 1 {
+            self.submit_score();
+        }
+    }
+
+    fn submit_score(&mut self) {
+        self.highscore_place = self.highscores.submit_game(&self.game, &self.name, self.play_time.as_secs());
+        if self.highscore_place.is_some() {
+            // the terminal is ours until the game's closed, so the error can't go there
+            let _ = self.highscores.save_default();
+        }
+    }
+
+    // playing alone the snake goes by the player's name, together they're numbered
+    fn snake_name(&self, snake: usize) -> String {
+        let names = if self.config.players == 1 { std::slice::from_ref(&self.name) } else { &[] };
+        self.game.snake_name(names, snake)
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = draw_board(&self.game, self.glyphs, self.color);
+        lines.push(String::new());
+        let scores: Vec<String> = self.game.get_scores().into_iter().enumerate().map(|(snake, score)| {
+            let color = Some(PLAYER_COLORS[snake]).filter(|_| self.color && self.game.get_players() > 1);
+            paint(&format!("{}: {}", self.snake_name(snake), score), color)
+        }).collect();
+        lines.push(scores.join("   "));
+        let status = if self.game.is_over() {
+            let result = match self.game.get_winner() {
+                Some(winner) => format!("{} WINS", self.snake_name(winner)),
+                None if self.game.get_players() > 1 => "DRAW".to_string(),
+                None => match self.highscore_place {
+                    Some(place) => format!("#{} in the high scores", place + 1),
+                    None => String::new()
+                }
+            };
+            format!("GAME OVER {}  -  R to restart, Q to quit", result)
+        } else if self.paused {
+            "PAUSED  -  P to resume, Q to quit".to_string()
+        } else {
+            format!("tick {}  -  P to pause, Q to quit", self.game.get_tick())
+        };
+        lines.push(status);
+        lines
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let config = load_config(args)?;
+    let mut game = Game::new(config, args)?;
+    let terminal = Terminal::open().map_err(|err| format!("snek-term needs a terminal: {}", err))?;
+
+    let (sender, keys) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buffer = [0; 32];
+        let mut pending = Vec::new();
+        while let Ok(read @ 1..) = stdin.read(&mut buffer) {
+            pending.extend_from_slice(&buffer[..read]);
+            if parse_keys(&mut pending).into_iter().any(|key| sender.send(key).is_err()) {
+                break;
+            }
+        }
+    });
+
+    let tick_length = |game: &GameState| Duration::from_secs_f64(1.0 / game.get_tick_rate().max(1) as f64);
+    let mut next_tick = Instant::now() + tick_length(&game.game);
+    loop {
+        terminal.draw(&game.lines()).map_err(|err| err.to_string())?;
+        match keys.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+            Ok(key) => {
+                if !game.on_key(key)? {
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(())
+        }
+        let length = tick_length(&game.game);
+        if !game.paused {
+            game.play_time += length;
+            game.tick();
+        }
+        // a slow terminal drops ticks rather than rushing to catch up
+        next_tick = (next_tick + length).max(Instant::now());
+    }
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("usage: snek-term [--seed <seed>] [--name <name>] [--config <file>] [--difficulty <easy|normal|hard>]");
+            eprintln!("                 [--ascii] [--no-color] [--radius <n>] [--holes-num <n>] [--holes-interval <n>]");
+            eprintln!("                 [--tick-rate <n>] [--speed-curve <curve>] [--speed-by <score|length>] [--level <file>]");
+            eprintln!("                 [--island hexagon|generated] [--controls relative|absolute] [--players <1-2>]");
+            eprintln!("                 [--rivals <n>] [--rival-difficulty <easy|normal|hard>]");
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snek::level::Level;
+
+    #[test]
+    fn test_parse_args() {
+        let args = |list: &[&str]| Args::parse(list.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&["snek-term"]), Ok(Args::default()));
+        let parsed = args(&["snek-term", "--ascii", "--no-color", "--seed", "5", "--players", "2"]).unwrap();
+        assert!(parsed.ascii && !parsed.color);
+        assert_eq!(parsed.seed, Some(5));
+        assert_eq!(parsed.overrides, vec![("players".to_string(), "2".to_string())]);
+        assert_eq!(args(&["snek-term", "--difficulty", "hard"]).unwrap().difficulty, Some(Difficulty::Hard));
+        assert!(args(&["snek-term", "--hex-side", "20"]).is_err());
+        assert!(args(&["snek-term", "--seed"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let parse = |bytes: &[u8]| parse_keys(&mut bytes.to_vec());
+        assert_eq!(parse(b"aQ"), vec![Key::Char('a'), Key::Char('q')]);
+        assert_eq!(parse(b"\x1b[D\x1b[Cp"), vec![Key::Left, Key::Right, Key::Char('p')]);
+        assert_eq!(parse(&[CTRL_C]), vec![Key::Quit]);
+        // up, then F5, then F1
+        assert_eq!(parse(b"\x1b[A\x1b[15~d\x1bOP"), vec![Key::Char('d')]);
+
+        // an arrow split across reads is put back together, whichever byte it's split after
+        for split in 1..3 {
+            let mut pending = b"a\x1b[D"[..split + 1].to_vec();
+            assert_eq!(parse_keys(&mut pending), vec![Key::Char('a')]);
+            pending.extend_from_slice(&b"a\x1b[D"[split + 1..]);
+            assert_eq!(parse_keys(&mut pending), vec![Key::Left]);
+            assert!(pending.is_empty());
+        }
+        // an escape that turns out to be on its own is dropped with the next key
+        let mut pending = b"\x1b".to_vec();
+        assert_eq!(parse_keys(&mut pending), Vec::new());
+        pending.push(b'p');
+        assert_eq!(parse_keys(&mut pending), vec![Key::Char('p')]);
+
+        assert_eq!(key_turn(Key::Left, ControlScheme::Relative), Some((0, Turn::Left)));
+        assert_eq!(key_turn(Key::Char('l'), ControlScheme::Relative), Some((1, Turn::Right)));
+        assert_eq!(key_turn(Key::Left, ControlScheme::Absolute), None);
+        assert_eq!(key_turn(Key::Char('x'), ControlScheme::Absolute), Some((0, Turn::Towards(Direction::SouthEast))));
+        assert_eq!(key_turn(Key::Char('p'), ControlScheme::Relative), None);
+    }
+
+    #[test]
+    fn test_draw_board() {
+        let level = Level::parse("direction: East\nmap:\n . ~ . .\n. . S @ .\n . . . .\n").unwrap();
+        let game = GameState::from_level(&GameConfig::default(), &level, 0);
+        // the level file's own layout comes back, with the snake drawn in and the void around it
+        assert_eq!(draw_board(&game, ASCII, false), vec!["", "   .   . .", "  o o O @ .", "   . . . .", ""]);
+        assert_eq!(draw_board(&game, UNICODE, false)[2], "  ○ ○ ◉ ● ·");
+        let colored = draw_board(&game, ASCII, true);
+        assert!(colored[2].contains(&paint("@", Some(APPLE_COLOR))));
+    }
+}
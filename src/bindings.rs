@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use ggez::event::{Button, KeyCode};
+use serde::Deserialize;
+use snek::config::ControlScheme;
+use snek::direction::{Direction, Turn};
+
+// how far the stick has to be pushed before it turns the snake
+const STICK_DEAD_ZONE: f32 = 0.5;
+
+// everything a key or a gamepad button can be bound to; what an action does depends on the screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    TurnLeft,
+    TurnRight,
+    West,
+    NorthWest,
+    NorthEast,
+    East,
+    SouthEast,
+    SouthWest,
+    Start,
+    Pause,
+    Back,
+    Restart,
+    Title,
+    Controls,
+    PlaybackPause,
+    Step,
+    FastForward,
+    QuickSave,
+    QuickLoad,
+}
+
+impl Action {
+    pub const ALL: [Action; 19] = [Action::TurnLeft, Action::TurnRight, Action::West, Action::NorthWest, Action::NorthEast,
+        Action::East, Action::SouthEast, Action::SouthWest, Action::Start, Action::Pause, Action::Back, Action::Restart,
+        Action::Title, Action::Controls, Action::PlaybackPause, Action::Step, Action::FastForward,
+        Action::QuickSave, Action::QuickLoad];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::TurnLeft => "turn_left",
+            Action::TurnRight => "turn_right",
+            Action::West => "west",
+            Action::NorthWest => "north_west",
+            Action::NorthEast => "north_east",
+            Action::East => "east",
+            Action::SouthEast => "south_east",
+            Action::SouthWest => "south_west",
+            Action::Start => "start",
+            Action::Pause => "pause",
+            Action::Back => "back",
+            Action::Restart => "restart",
+            Action::Title => "title",
+            Action::Controls => "controls",
+            Action::PlaybackPause => "playback_pause",
+            Action::Step => "step",
+            Action::FastForward => "fast_forward",
+            Action::QuickSave => "quick_save",
+            Action::QuickLoad => "quick_load",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().find(|action| action.name() == name).copied()
+    }
+
+    pub fn is_turn(&self) -> bool {
+        turn_for_action(*self, ControlScheme::Relative).or_else(|| turn_for_action(*self, ControlScheme::Absolute)).is_some()
+    }
+}
+
+macro_rules! names {
+    ($kind:ident: $($name:ident),*) => {
+        [$((stringify!($name), $kind::$name)),*]
+    };
+}
+
+const KEYS: [(&str, KeyCode); 91] = names!(KeyCode: A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9, NumpadEnter,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Left, Right, Up, Down, Escape, Return, Space, Tab, Back, Delete, Insert, Home, End, PageUp, PageDown,
+    Comma, Period, Slash, Semicolon, Apostrophe, LBracket, RBracket, Backslash, Minus, Equals, Grave,
+    LShift, RShift, LControl, RControl, LAlt, RAlt);
+
+const BUTTONS: [(&str, Button); 19] = names!(Button: South, East, North, West, C, Z,
+    LeftTrigger, LeftTrigger2, RightTrigger, RightTrigger2, Select, Start, Mode, LeftThumb, RightThumb,
+    DPadUp, DPadDown, DPadLeft, DPadRight);
+
+fn default_keys() -> Vec<(KeyCode, Action)> {
+    vec![
+        (KeyCode::A, Action::TurnLeft), (KeyCode::Left, Action::TurnLeft),
+        (KeyCode::D, Action::TurnRight), (KeyCode::Right, Action::TurnRight),
+        // the absolute keys sit around S and 5 the way the six neighbours sit around a hex
+        (KeyCode::A, Action::West), (KeyCode::Numpad4, Action::West),
+        (KeyCode::W, Action::NorthWest), (KeyCode::Numpad7, Action::NorthWest),
+        (KeyCode::E, Action::NorthEast), (KeyCode::Numpad9, Action::NorthEast),
+        (KeyCode::D, Action::East), (KeyCode::Numpad6, Action::East),
+        (KeyCode::X, Action::SouthEast), (KeyCode::Numpad3, Action::SouthEast),
+        (KeyCode::Z, Action::SouthWest), (KeyCode::Numpad1, Action::SouthWest),
+        (KeyCode::Return, Action::Start), (KeyCode::Space, Action::Start),
+        (KeyCode::P, Action::Pause), (KeyCode::Escape, Action::Pause),
+        (KeyCode::Escape, Action::Back),
+        (KeyCode::R, Action::Restart),
+        (KeyCode::Q, Action::Title),
+        (KeyCode::C, Action::Controls),
+        (KeyCode::Space, Action::PlaybackPause),
+        (KeyCode::Right, Action::Step), (KeyCode::Period, Action::Step),
+        (KeyCode::F, Action::FastForward),
+        (KeyCode::F5, Action::QuickSave),
+        (KeyCode::F9, Action::QuickLoad),
+    ]
+}
+
+// the second player's keys are laid out like the first one's, around J instead of S
+fn default_other_keys() -> Vec<(usize, KeyCode, Action)> {
+    vec![
+        (1, KeyCode::J, Action::TurnLeft), (1, KeyCode::L, Action::TurnRight),
+        (1, KeyCode::H, Action::West), (1, KeyCode::U, Action::NorthWest), (1, KeyCode::I, Action::NorthEast),
+        (1, KeyCode::K, Action::East), (1, KeyCode::M, Action::SouthEast), (1, KeyCode::N, Action::SouthWest),
+    ]
+}
+
+fn default_buttons() -> Vec<(Button, Action)> {
+    vec![
+        (Button::DPadLeft, Action::TurnLeft), (Button::LeftTrigger, Action::TurnLeft),
+        (Button::DPadRight, Action::TurnRight), (Button::RightTrigger, Action::TurnRight),
+        (Button::South, Action::Start), (Button::Start, Action::Start),
+        (Button::Start, Action::Pause),
+        (Button::East, Action::Back),
+        (Button::South, Action::Restart),
+        (Button::Select, Action::Title),
+        (Button::North, Action::Controls),
+        (Button::South, Action::PlaybackPause),
+        (Button::DPadRight, Action::Step),
+        (Button::RightTrigger2, Action::FastForward),
+    ]
+}
+
+// the bindings file lists the keys and buttons of every action it changes, the others keep their defaults:
+//
+//   [keys]
+//   turn_left = ["J", "Left"]
+//   pause = ["P"]
+//
+//   [gamepad]
+//   start = ["Start"]
+//
+//   [player2]
+//   turn_left = ["Comma"]
+//
+// The keys of the first player also work the menus, the other players' sections only take turning actions.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BindingsFile {
+    keys: HashMap<String, Vec<String>>,
+    gamepad: HashMap<String, Vec<String>>,
+    player2: HashMap<String, Vec<String>>,
+    player3: HashMap<String, Vec<String>>,
+    player4: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    keys: Vec<(KeyCode, Action)>,
+    // the turning keys of every player but the first, by player index
+    other_keys: Vec<(usize, KeyCode, Action)>,
+    buttons: Vec<(Button, Action)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings { keys: default_keys(), other_keys: default_other_keys(), buttons: default_buttons() }
+    }
+}
+
+impl Bindings {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("snek").join("bindings.toml"))
+    }
+
+    pub fn parse(text: &str) -> Result<Bindings, String> {
+        let file: BindingsFile = toml::from_str(text).map_err(|err| err.to_string())?;
+        let mut bindings = Bindings::default();
+        rebind(&mut bindings.keys, &file.keys, &KEYS, "key")?;
+        rebind(&mut bindings.buttons, &file.gamepad, &BUTTONS, "gamepad button")?;
+        for (player, listed) in [(1, &file.player2), (2, &file.player3), (3, &file.player4)] {
+            let mut keys = bindings.other_keys.iter().filter(|(other, _, _)| *other == player).map(|(_, key, action)| (*key, *action)).collect();
+            rebind(&mut keys, listed, &KEYS, "key")?;
+            if let Some((_, action)) = keys.iter().find(|(_, action)| !action.is_turn()) {
+                return Err(format!("player{} can only have turning actions, not {}", player + 1, action.name()));
+            }
+            bindings.other_keys.retain(|(other, _, _)| *other != player);
+            bindings.other_keys.extend(keys.into_iter().map(|(key, action)| (player, key, action)));
+        }
+        Ok(bindings)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Bindings> {
+        let text = fs::read_to_string(path)?;
+        Bindings::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn key_actions(&self, keycode: KeyCode) -> Vec<Action> {
+        self.keys.iter().filter(|(key, _)| *key == keycode).map(|(_, action)| *action).collect()
+    }
+
+    // the players after the first with what the key does for them
+    pub fn other_player_actions(&self, keycode: KeyCode) -> Vec<(usize, Action)> {
+        self.other_keys.iter().filter(|(_, key, _)| *key == keycode).map(|(player, _, action)| (*player, *action)).collect()
+    }
+
+    pub fn button_actions(&self, button: Button) -> Vec<Action> {
+        self.buttons.iter().filter(|(other, _)| *other == button).map(|(_, action)| *action).collect()
+    }
+}
+
+// replaces the inputs of every action listed in the file
+fn rebind<T: Copy>(bound: &mut Vec<(T, Action)>, listed: &HashMap<String, Vec<String>>, names: &[(&str, T)], kind: &str) -> Result<(), String> {
+    for (action_name, inputs) in listed {
+        let action = Action::from_name(action_name).ok_or(format!("unknown action: {}", action_name))?;
+        bound.retain(|(_, other)| *other != action);
+        for input in inputs {
+            let (_, input) = names.iter().find(|(name, _)| name == input).ok_or(format!("unknown {}: {}", kind, input))?;
+            bound.push((*input, action));
+        }
+    }
+    Ok(())
+}
+
+pub fn turn_for_action(action: Action, controls: ControlScheme) -> Option<Turn> {
+    match (controls, action) {
+        (ControlScheme::Relative, Action::TurnLeft) => Some(Turn::Left),
+        (ControlScheme::Relative, Action::TurnRight) => Some(Turn::Right),
+        (ControlScheme::Absolute, Action::West) => Some(Turn::Towards(Direction::West)),
+        (ControlScheme::Absolute, Action::NorthWest) => Some(Turn::Towards(Direction::NorthWest)),
+        (ControlScheme::Absolute, Action::NorthEast) => Some(Turn::Towards(Direction::NorthEast)),
+        (ControlScheme::Absolute, Action::East) => Some(Turn::Towards(Direction::East)),
+        (ControlScheme::Absolute, Action::SouthEast) => Some(Turn::Towards(Direction::SouthEast)),
+        (ControlScheme::Absolute, Action::SouthWest) => Some(Turn::Towards(Direction::SouthWest)),
+        _ => None
+    }
+}
+
+// where the stick points the snake, with y going up; sideways turns it with relative controls,
+// with absolute ones it points the head at the closest of the six directions
+pub fn stick_turn(x: f32, y: f32, controls: ControlScheme) -> Option<Turn> {
+    if x.hypot(y) < STICK_DEAD_ZONE {
+        return None;
+    }
+    match controls {
+        ControlScheme::Relative if x <= -STICK_DEAD_ZONE => Some(Turn::Left),
+        ControlScheme::Relative if x >= STICK_DEAD_ZONE => Some(Turn::Right),
+        ControlScheme::Relative => None,
+        ControlScheme::Absolute => {
+            let sectors = [Direction::East, Direction::NorthEast, Direction::NorthWest, Direction::West, Direction::SouthWest, Direction::SouthEast];
+            let sector = (y.atan2(x).to_degrees() / 60.0).round() as i32;
+            Some(Turn::Towards(sectors[sector.rem_euclid(6) as usize]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_bindings() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.key_actions(KeyCode::A), vec![Action::TurnLeft, Action::West]);
+        assert_eq!(bindings.key_actions(KeyCode::Escape), vec![Action::Pause, Action::Back]);
+        assert!(bindings.key_actions(KeyCode::L).is_empty());
+        assert_eq!(bindings.other_player_actions(KeyCode::L), vec![(1, Action::TurnRight)]);
+        assert_eq!(bindings.button_actions(Button::DPadLeft), vec![Action::TurnLeft]);
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(action));
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let bindings = Bindings::parse("[keys]\nturn_left = [\"J\", \"Numpad4\"]\n\n[gamepad]\npause = [\"Select\"]\n").unwrap();
+        assert_eq!(bindings.key_actions(KeyCode::J), vec![Action::TurnLeft]);
+        assert_eq!(bindings.key_actions(KeyCode::A), vec![Action::West]);
+        assert_eq!(bindings.key_actions(KeyCode::Left), vec![]);
+        assert_eq!(bindings.button_actions(Button::Select), vec![Action::Title, Action::Pause]);
+        assert_eq!(bindings.button_actions(Button::Start), vec![Action::Start]);
+        assert_eq!(Bindings::parse("").unwrap(), Bindings::default());
+
+        assert!(Bindings::parse("[keys]\njump = [\"J\"]\n").is_err());
+        assert!(Bindings::parse("[keys]\npause = [\"Pause\"]\n").is_err());
+        assert!(Bindings::parse("[mouse]\npause = [\"Left\"]\n").is_err());
+
+        let bindings = Bindings::parse("[player2]\nturn_left = [\"Comma\"]\n\n[player3]\neast = [\"Numpad6\"]\n").unwrap();
+        assert_eq!(bindings.other_player_actions(KeyCode::Comma), vec![(1, Action::TurnLeft)]);
+        assert!(bindings.other_player_actions(KeyCode::J).is_empty());
+        assert_eq!(bindings.other_player_actions(KeyCode::L), vec![(1, Action::TurnRight)]);
+        assert_eq!(bindings.other_player_actions(KeyCode::Numpad6), vec![(2, Action::East)]);
+        assert!(Bindings::parse("[player2]\npause = [\"P\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_turn_for_action() {
+        assert_eq!(turn_for_action(Action::TurnLeft, ControlScheme::Relative), Some(Turn::Left));
+        assert_eq!(turn_for_action(Action::TurnRight, ControlScheme::Relative), Some(Turn::Right));
+        assert_eq!(turn_for_action(Action::West, ControlScheme::Relative), None);
+        assert_eq!(turn_for_action(Action::NorthEast, ControlScheme::Absolute), Some(Turn::Towards(Direction::NorthEast)));
+        assert_eq!(turn_for_action(Action::TurnLeft, ControlScheme::Absolute), None);
+        assert_eq!(turn_for_action(Action::Pause, ControlScheme::Relative), None);
+    }
+
+    #[test]
+    fn test_stick_turn() {
+        assert_eq!(stick_turn(0.1, -0.2, ControlScheme::Relative), None);
+        assert_eq!(stick_turn(-0.9, 0.1, ControlScheme::Relative), Some(Turn::Left));
+        assert_eq!(stick_turn(0.9, 0.1, ControlScheme::Relative), Some(Turn::Right));
+        assert_eq!(stick_turn(0.0, 0.9, ControlScheme::Relative), None);
+        assert_eq!(stick_turn(0.9, 0.1, ControlScheme::Absolute), Some(Turn::Towards(Direction::East)));
+        assert_eq!(stick_turn(-0.4, 0.8, ControlScheme::Absolute), Some(Turn::Towards(Direction::NorthWest)));
+        assert_eq!(stick_turn(-0.9, -0.1, ControlScheme::Absolute), Some(Turn::Towards(Direction::West)));
+        assert_eq!(stick_turn(0.4, -0.8, ControlScheme::Absolute), Some(Turn::Towards(Direction::SouthEast)));
+    }
+}
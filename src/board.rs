@@ -0,0 +1,42 @@
+use std::collections::HashSet;
+use indexmap::map::IndexMap;
+use crate::hexagon::HexCoordinates;
+use crate::tile::Tile;
+
+// all the land tiles that can be walked to from the given one, it included
+pub fn reachable_land(board: &IndexMap<HexCoordinates, Tile>, from: &HexCoordinates) -> HashSet<HexCoordinates> {
+    let mut reached = HashSet::new();
+    if !matches!(board.get(from), Some(tile) if !tile.is_hole()) {
+        return reached;
+    }
+    let mut stack = vec![from.clone()];
+    reached.insert(from.clone());
+    while let Some(coord) = stack.pop() {
+        for neighbour in coord.neighbors() {
+            if matches!(board.get(&neighbour), Some(tile) if !tile.is_hole()) && !reached.contains(&neighbour) {
+                reached.insert(neighbour.clone());
+                stack.push(neighbour);
+            }
+        }
+    }
+    reached
+}
+
+pub fn land_count(board: &IndexMap<HexCoordinates, Tile>) -> usize {
+    board.values().filter(|tile| !tile.is_hole()).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Level;
+
+    #[test]
+    fn test_reachable_land() {
+        let level = Level::parse("map:\n. . S . . ~ . .\n").unwrap();
+        assert_eq!(land_count(&level.board), 7);
+        assert_eq!(reachable_land(&level.board, &level.start).len(), 5);
+        let hole = level.board.iter().find(|(_, tile)| tile.is_hole()).unwrap().0;
+        assert!(reachable_land(&level.board, hole).is_empty());
+    }
+}
@@ -0,0 +1,196 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::config::GameConfig;
+use crate::protocol::{self, ClientMessage, Connection, Event, PROTOCOL_VERSION, ServerMessage};
+
+// how many messages a spectator can be behind before it's dropped, about ten seconds of ticks
+const SPECTATOR_BACKLOG: usize = 256;
+// a spectator that takes longer than this to take a message is gone
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Sends a game to everyone watching it as it's played. The ones who come in late get everything from the
+// welcome on, which they catch up with by playing it through.
+#[derive(Clone)]
+pub struct Broadcast {
+    shared: Arc<Mutex<Shared>>,
+    // the spectators coming and going, for whoever's running it to show
+    events: Sender<Event>,
+}
+
+#[derive(Default)]
+struct Shared {
+    // every message of the game being played, the welcome first
+    history: Vec<ServerMessage>,
+    // every spectator has a thread of its own writing to it, so a slow one never holds up the game
+    spectators: Vec<(String, SyncSender<ServerMessage>)>,
+}
+
+impl Broadcast {
+    pub fn new(events: Sender<Event>) -> Broadcast {
+        Broadcast { shared: Arc::default(), events }
+    }
+
+    // for a game played in the window, the spectators connect to the address with `snek --watch`
+    pub fn listen(addr: impl ToSocketAddrs, events: Sender<Event>) -> io::Result<(Broadcast, SocketAddr)> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let broadcast = Broadcast::new(events);
+        let spectators = broadcast.clone();
+        thread::spawn(move || accept(listener, None, spectators));
+        Ok((broadcast, local_addr))
+    }
+
+    pub fn add(&self, name: String, mut connection: Connection) {
+        let mut shared = self.shared.lock().unwrap();
+        let history = shared.history.clone();
+        let (sender, messages) = mpsc::sync_channel(SPECTATOR_BACKLOG);
+        thread::spawn(move || {
+            // the game so far, then everything after it as it's played; a failed write drops the receiver
+            let _ = connection.set_write_timeout(Some(WRITE_TIMEOUT))
+                .and_then(|_| history.iter().try_for_each(|message| connection.send(message)))
+                .and_then(|_| messages.iter().try_for_each(|message| connection.send(&message)));
+        });
+        shared.spectators.push((name, sender));
+    }
+
+    // nobody listening is no reason to stop
+    pub(crate) fn event(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+
+    pub fn get_spectators(&self) -> usize {
+        self.shared.lock().unwrap().spectators.len()
+    }
+
+    // a new game, the one before is forgotten
+    pub fn start(&self, seed: u64, config: &GameConfig, level: Option<String>, names: Vec<String>) {
+        let config = GameConfig { level: None, ..config.clone() };
+        let welcome = ServerMessage::Welcome { version: PROTOCOL_VERSION, player: None, names, seed, config, level };
+        self.shared.lock().unwrap().history.clear();
+        self.send(&welcome);
+    }
+
+    // never waits on a spectator: one that has left or fallen too far behind is dropped
+    pub fn send(&self, message: &ServerMessage) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.history.push(message.clone());
+        shared.spectators.retain(|(name, sender)| match sender.try_send(message.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                self.event(Event::StoppedWatching { name: name.clone() });
+                false
+            }
+        });
+    }
+}
+
+// Takes every connection on the listener and sees who it is: the spectators go to the broadcast, and the players
+// to the sender if there is one; without one there's only watching.
+pub(crate) fn accept(listener: TcpListener, players: Option<Sender<(String, Connection)>>, broadcast: Broadcast) {
+    for stream in listener.incoming().flatten() {
+        let players = players.clone();
+        let broadcast = broadcast.clone();
+        // a slow one shouldn't keep the others waiting
+        thread::spawn(move || {
+            // gone already
+            let Ok(addr) = stream.peer_addr() else {
+                return;
+            };
+            if let Err(err) = greet(stream, addr, players, &broadcast) {
+                broadcast.event(Event::TurnedAway { addr, reason: err.to_string() });
+            }
+        });
+    }
+}
+
+fn greet(stream: TcpStream, addr: SocketAddr, players: Option<Sender<(String, Connection)>>, broadcast: &Broadcast) -> io::Result<()> {
+    let (mut connection, greeting) = protocol::greeting::<ClientMessage>(stream)?;
+    let error = match (greeting, players) {
+        (Ok(ClientMessage::Join { version, .. } | ClientMessage::Watch { version, .. }), _) if version != PROTOCOL_VERSION => {
+            format!("this end speaks version {} of the protocol, not {}", PROTOCOL_VERSION, version)
+        }
+        (Ok(ClientMessage::Join { name, .. }), Some(players)) => {
+            broadcast.event(Event::WantsToPlay { name: name.clone(), addr });
+            // the server's gone if nobody takes it
+            let _ = players.send((name, connection));
+            return Ok(());
+        }
+        (Ok(ClientMessage::Join { .. }), None) => "this game is only for watching".to_string(),
+        (Ok(ClientMessage::Watch { name, .. }), _) => {
+            broadcast.event(Event::Watching { name: name.clone(), addr });
+            broadcast.add(name, connection);
+            return Ok(());
+        }
+        (Ok(_), _) => "expected a join or watch message".to_string(),
+        (Err(err), _) => err.to_string()
+    };
+    // it's leaving either way
+    let _ = connection.send(&ServerMessage::Error { message: error.clone() });
+    Err(io::Error::other(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direction::Turn;
+
+    fn watch(addr: SocketAddr) -> Connection {
+        let mut connection = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        connection.send(&ClientMessage::Watch { version: PROTOCOL_VERSION, name: "fan".to_string() }).unwrap();
+        connection
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let (events, received) = mpsc::channel();
+        let (broadcast, addr) = Broadcast::listen("127.0.0.1:0", events).unwrap();
+        broadcast.start(4, &GameConfig::default(), None, vec!["edi".to_string()]);
+        broadcast.send(&ServerMessage::Tick { tick: 0, turns: vec![vec![Turn::Left]] });
+
+        // a late one gets the game so far
+        let mut late = watch(addr);
+        assert!(matches!(late.receive().unwrap(), Some(ServerMessage::Welcome { player: None, seed: 4, .. })));
+        assert!(matches!(late.receive().unwrap(), Some(ServerMessage::Tick { tick: 0, .. })));
+        while broadcast.get_spectators() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        broadcast.send(&ServerMessage::Tick { tick: 1, turns: vec![Vec::new()] });
+        assert!(matches!(late.receive().unwrap(), Some(ServerMessage::Tick { tick: 1, .. })));
+
+        let mut player = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        player.send(&ClientMessage::Join { version: PROTOCOL_VERSION, name: "edi".to_string() }).unwrap();
+        assert!(matches!(player.receive().unwrap(), Some(ServerMessage::Error { .. })));
+        assert!(matches!(received.recv().unwrap(), Event::Watching { name, .. } if name == "fan"));
+        assert!(matches!(received.recv().unwrap(), Event::TurnedAway { reason, .. } if reason == "this game is only for watching"));
+
+        // the ones that left are dropped on the next message
+        drop(late);
+        while broadcast.get_spectators() > 0 {
+            broadcast.send(&ServerMessage::GameOver { winner: None, scores: vec![0] });
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(received.recv().unwrap(), Event::StoppedWatching { name: "fan".to_string() });
+    }
+
+    #[test]
+    fn test_stalled_spectator() {
+        let (broadcast, addr) = Broadcast::listen("127.0.0.1:0", mpsc::channel().0).unwrap();
+        broadcast.start(4, &GameConfig::default(), None, vec!["edi".to_string()]);
+        // connected, but never reading a thing
+        let _stalled = watch(addr);
+        while broadcast.get_spectators() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let game = crate::game::GameState::new(&GameConfig::default(), 4);
+        let snapshot = ServerMessage::Snapshot(crate::protocol::Snapshot::of(&game));
+        // far more than the socket takes in, and none of it waits for the spectator
+        for _ in 0..20_000 {
+            broadcast.send(&snapshot);
+        }
+        assert_eq!(broadcast.get_spectators(), 0);
+    }
+}
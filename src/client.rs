@@ -0,0 +1,164 @@
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use crate::direction::Turn;
+use crate::game::GameState;
+use crate::level::Level;
+use crate::protocol::{self, ClientMessage, Connection, PROTOCOL_VERSION, ServerMessage, Snapshot};
+
+// a player's or a spectator's end of a game run elsewhere
+pub struct NetClient {
+    // the snake this client steers, None when only watching
+    pub player: Option<usize>,
+    pub names: Vec<String>,
+    connection: Connection,
+    messages: Receiver<ServerMessage>,
+}
+
+impl NetClient {
+    // waits until the server starts the game, which is once every player has joined
+    pub fn connect(addr: impl ToSocketAddrs, name: &str) -> io::Result<(NetClient, Level, GameState)> {
+        NetClient::open(addr, ClientMessage::Join { version: PROTOCOL_VERSION, name: name.to_string() })
+    }
+
+    // the game being played there, or the next one if there's none yet; a spectator stays on for the games after it
+    pub fn watch(addr: impl ToSocketAddrs, name: &str) -> io::Result<(NetClient, Level, GameState)> {
+        NetClient::open(addr, ClientMessage::Watch { version: PROTOCOL_VERSION, name: name.to_string() })
+    }
+
+    fn open(addr: impl ToSocketAddrs, hello: ClientMessage) -> io::Result<(NetClient, Level, GameState)> {
+        let mut connection = Connection::new(TcpStream::connect(addr)?)?;
+        connection.send(&hello)?;
+        let (player, names, level, game) = match connection.receive()? {
+            Some(ServerMessage::Welcome { version, player, names, seed, config, level }) => {
+                if version != PROTOCOL_VERSION {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("the server speaks version {} of the protocol", version)));
+                }
+                if player.is_some_and(|player| player >= config.players) || names.len() != config.players {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "the server's welcome doesn't add up"));
+                }
+                let (level, game) = protocol::start_game(&config, seed, level.as_deref())?;
+                (player, names, level, game)
+            }
+            Some(ServerMessage::Error { message }) => return Err(io::Error::other(message)),
+            Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "the server didn't welcome us")),
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the server closed the connection"))
+        };
+
+        let (sender, messages) = mpsc::channel();
+        let mut reader = connection.try_clone()?;
+        thread::spawn(move || loop {
+            let message = match reader.receive() {
+                Ok(Some(message)) => message,
+                Ok(None) => ServerMessage::Error { message: "the server closed the connection".to_string() },
+                Err(err) => ServerMessage::Error { message: err.to_string() },
+            };
+            let last = match message {
+                ServerMessage::Error { .. } => true,
+                ServerMessage::GameOver { .. } => player.is_some(),
+                _ => false
+            };
+            if sender.send(message).is_err() || last {
+                break;
+            }
+        });
+        Ok((NetClient { player, names, connection, messages }, level, game))
+    }
+
+    pub fn send_turns(&mut self, turns: Vec<Turn>) -> io::Result<()> {
+        self.connection.send(&ClientMessage::Input { turns })
+    }
+
+    // the messages that came in since the last time, without waiting for any
+    pub fn poll(&self) -> Vec<ServerMessage> {
+        self.messages.try_iter().collect()
+    }
+}
+
+// plays a message from the server on the client's copy of the game
+pub fn apply(game: &mut GameState, message: &ServerMessage) -> Result<(), String> {
+    match message {
+        ServerMessage::Tick { tick, turns } => {
+            if *tick != game.get_tick() {
+                return Err(format!("got tick {} while at tick {}", tick, game.get_tick()));
+            }
+            game.step_players(turns);
+        }
+        ServerMessage::Snapshot(snapshot) => {
+            if *snapshot != Snapshot::of(game) {
+                return Err(format!("out of sync with the server at tick {}", snapshot.tick));
+            }
+        }
+        ServerMessage::Error { message } => return Err(message.clone()),
+        // a new game is for whoever has the client to start
+        ServerMessage::Welcome { .. } | ServerMessage::GameOver { .. } => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Duration;
+    use crate::config::GameConfig;
+    use crate::server::Server;
+
+    #[test]
+    fn test_client() {
+        let dir = std::env::temp_dir().join(format!("snek-client-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("strip.level");
+        std::fs::write(&path, "direction: East\nmap:\n . . . . . .\n. . . S @ . .\n . . . . . .\n").unwrap();
+        let config = GameConfig { level: Some(path), holes_num: 0, ..GameConfig::default() };
+        let mut server = Server::bind("127.0.0.1:0", &config, mpsc::channel().0).unwrap();
+        server.set_tick_length(Duration::from_millis(5));
+        let addr = server.local_addr().unwrap();
+        let handle = std::thread::spawn(move || server.play(1).unwrap());
+
+        let (client, level, mut game) = NetClient::connect(addr, "edi").unwrap();
+        assert_eq!(level.apples.len(), 1);
+        assert_eq!((client.player, client.names.clone()), (Some(0), vec!["edi".to_string()]));
+        let mut over = false;
+        while !over {
+            for message in client.poll() {
+                apply(&mut game, &message).unwrap();
+                over |= matches!(message, ServerMessage::GameOver { .. });
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // straight over the apple and off the end of the strip
+        assert_eq!(Snapshot::of(&game), handle.join().unwrap());
+        assert_eq!(game.get_score(), 1);
+
+        assert!(apply(&mut game, &ServerMessage::Tick { tick: 0, turns: Vec::new() }).is_err());
+        let mut snapshot = Snapshot::of(&game);
+        snapshot.scores[0] += 1;
+        assert_eq!(apply(&mut game, &ServerMessage::Snapshot(snapshot.clone())),
+                   Err(format!("out of sync with the server at tick {}", snapshot.tick)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bad_welcome() {
+        // a server that welcomes with whatever it's given
+        let welcome = |player: Option<usize>, names: usize, config: GameConfig| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                let mut connection = Connection::new(listener.accept().unwrap().0).unwrap();
+                connection.receive::<ClientMessage>().unwrap();
+                let names = vec!["edi".to_string(); names];
+                let _ = connection.send(&ServerMessage::Welcome { version: PROTOCOL_VERSION, player, names, seed: 1, config, level: None });
+            });
+            NetClient::connect(addr, "edi").map(|_| ())
+        };
+        assert!(welcome(Some(0), 1, GameConfig::default()).is_ok());
+        let error = welcome(Some(0), 1, GameConfig { holes_interval: 0, ..GameConfig::default() }).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(welcome(Some(0), 0, GameConfig { players: 0, ..GameConfig::default() }).is_err());
+        assert!(welcome(Some(1), 1, GameConfig::default()).is_err());
+        assert!(welcome(None, 2, GameConfig::default()).is_err());
+    }
+}
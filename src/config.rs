@@ -0,0 +1,519 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::highscore::BoardSettings;
+use crate::island;
+use crate::level::Level;
+
+// how the tick rate grows from the configured one, written as "fixed", "linear:<per point>:<max rate>"
+// or "stepped:<every>:<step>:<max rate>"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SpeedCurve {
+    #[default]
+    Fixed,
+    Linear { per_point: f32, max_rate: u32 },
+    Stepped { every: i32, step: u32, max_rate: u32 },
+}
+
+impl SpeedCurve {
+    pub fn tick_rate(&self, base_rate: u32, points: i32) -> u32 {
+        let points = points.max(0);
+        match *self {
+            SpeedCurve::Fixed => base_rate,
+            SpeedCurve::Linear { per_point, max_rate } => {
+                // the float to int cast saturates, and so does everything after it
+                let rate = base_rate.saturating_add((points as f32 * per_point) as u32);
+                rate.min(max_rate.max(base_rate))
+            }
+            SpeedCurve::Stepped { every, step, max_rate } => {
+                let rate = base_rate.saturating_add(((points / every) as u32).saturating_mul(step));
+                rate.min(max_rate.max(base_rate))
+            }
+        }
+    }
+}
+
+impl FromStr for SpeedCurve {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<SpeedCurve, String> {
+        let parts: Vec<&str> = text.split(':').collect();
+        let invalid = || format!("invalid speed curve: {}", text);
+        let curve = match parts.as_slice() {
+            ["fixed"] => SpeedCurve::Fixed,
+            ["linear", per_point, max_rate] => SpeedCurve::Linear {
+                per_point: per_point.parse().map_err(|_| invalid())?,
+                max_rate: max_rate.parse().map_err(|_| invalid())?,
+            },
+            ["stepped", every, step, max_rate] => SpeedCurve::Stepped {
+                every: every.parse().map_err(|_| invalid())?,
+                step: step.parse().map_err(|_| invalid())?,
+                max_rate: max_rate.parse().map_err(|_| invalid())?,
+            },
+            _ => return Err(invalid())
+        };
+        match curve {
+            SpeedCurve::Linear { per_point, .. } if !per_point.is_finite() || per_point < 0.0 => Err(invalid()),
+            SpeedCurve::Stepped { every, .. } if every < 1 => Err(invalid()),
+            _ => Ok(curve)
+        }
+    }
+}
+
+impl fmt::Display for SpeedCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpeedCurve::Fixed => write!(f, "fixed"),
+            SpeedCurve::Linear { per_point, max_rate } => write!(f, "linear:{}:{}", per_point, max_rate),
+            SpeedCurve::Stepped { every, step, max_rate } => write!(f, "stepped:{}:{}:{}", every, step, max_rate),
+        }
+    }
+}
+
+impl TryFrom<String> for SpeedCurve {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<SpeedCurve, String> {
+        text.parse()
+    }
+}
+
+impl From<SpeedCurve> for String {
+    fn from(curve: SpeedCurve) -> String {
+        curve.to_string()
+    }
+}
+
+// what the speed curve counts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedBy {
+    #[default]
+    Score,
+    Length,
+}
+
+impl FromStr for SpeedBy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<SpeedBy, String> {
+        match text {
+            "score" => Ok(SpeedBy::Score),
+            "length" => Ok(SpeedBy::Length),
+            _ => Err(format!("invalid speed_by: {}", text))
+        }
+    }
+}
+
+impl fmt::Display for SpeedBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpeedBy::Score => write!(f, "score"),
+            SpeedBy::Length => write!(f, "length"),
+        }
+    }
+}
+
+// the island used when no level file is given
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IslandShape {
+    #[default]
+    Hexagon,
+    Generated,
+}
+
+impl FromStr for IslandShape {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<IslandShape, String> {
+        match text {
+            "hexagon" => Ok(IslandShape::Hexagon),
+            "generated" => Ok(IslandShape::Generated),
+            _ => Err(format!("invalid island: {}", text))
+        }
+    }
+}
+
+impl fmt::Display for IslandShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IslandShape::Hexagon => write!(f, "hexagon"),
+            IslandShape::Generated => write!(f, "generated"),
+        }
+    }
+}
+
+// relative keys turn the head left or right, absolute ones point it in one of the six directions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlScheme {
+    #[default]
+    Relative,
+    Absolute,
+}
+
+impl ControlScheme {
+    pub fn toggled(self) -> ControlScheme {
+        match self {
+            ControlScheme::Relative => ControlScheme::Absolute,
+            ControlScheme::Absolute => ControlScheme::Relative,
+        }
+    }
+}
+
+impl FromStr for ControlScheme {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<ControlScheme, String> {
+        match text {
+            "relative" => Ok(ControlScheme::Relative),
+            "absolute" => Ok(ControlScheme::Absolute),
+            _ => Err(format!("invalid controls: {}", text))
+        }
+    }
+}
+
+impl fmt::Display for ControlScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlScheme::Relative => write!(f, "relative"),
+            ControlScheme::Absolute => write!(f, "absolute"),
+        }
+    }
+}
+
+// picked on the title screen for the speed, and in the config for how far ahead the rivals look
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    pub fn apply(&self, config: &mut GameConfig) {
+        let (tick_rate, speed_curve) = match self {
+            Difficulty::Easy => (2, SpeedCurve::Fixed),
+            Difficulty::Normal => (2, SpeedCurve::Linear { per_point: 0.25, max_rate: 6 }),
+            Difficulty::Hard => (3, SpeedCurve::Stepped { every: 3, step: 1, max_rate: 10 }),
+        };
+        config.tick_rate = tick_rate;
+        config.speed_curve = speed_curve;
+        config.speed_by = SpeedBy::Score;
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Difficulty, String> {
+        Difficulty::ALL.iter().find(|difficulty| difficulty.name() == text).copied()
+            .ok_or(format!("unknown difficulty: {}", text))
+    }
+}
+
+pub const MAX_PLAYERS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub hex_side: f32,
+    pub radius: i32,
+    pub holes_num: i32,
+    pub holes_interval: i32,
+    pub tick_rate: u32,
+    pub speed_curve: SpeedCurve,
+    pub speed_by: SpeedBy,
+    pub level: Option<PathBuf>,
+    pub island: IslandShape,
+    pub controls: ControlScheme,
+    pub players: usize,
+    // computer-controlled snakes after the players
+    pub rivals: usize,
+    pub rival_difficulty: Difficulty,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            hex_side: 30.0,
+            radius: 9,
+            holes_num: 3,
+            holes_interval: 3,
+            tick_rate: 2,
+            speed_curve: SpeedCurve::Fixed,
+            speed_by: SpeedBy::Score,
+            level: None,
+            island: IslandShape::Hexagon,
+            controls: ControlScheme::Relative,
+            players: 1,
+            rivals: 0,
+            rival_difficulty: Difficulty::Normal,
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("snek").join("config.toml"))
+    }
+
+    pub fn parse(text: &str) -> Result<GameConfig, String> {
+        let config: GameConfig = toml::from_str(text).map_err(|err| err.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> io::Result<GameConfig> {
+        let text = fs::read_to_string(path)?;
+        GameConfig::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    // the config file given on the command line, or else the player's own if there is one, with the overrides on top;
+    // the window and the terminal play by the same one
+    pub fn load_with_overrides(path: Option<&Path>, overrides: &[(String, String)]) -> Result<GameConfig, String> {
+        let path = path.map(Path::to_path_buf).or_else(|| GameConfig::default_path().filter(|path| path.exists()));
+        GameConfig::load_with_overrides_from(path.as_deref(), overrides)
+    }
+
+    // only the config file given, if any, with the overrides on top; the player's own config.toml is left out
+    pub fn load_with_overrides_from(path: Option<&Path>, overrides: &[(String, String)]) -> Result<GameConfig, String> {
+        let mut config = match path {
+            Some(path) => GameConfig::load(path).map_err(|err| format!("could not load config {}: {}", path.display(), err))?,
+            None => GameConfig::default()
+        };
+        for (key, value) in overrides {
+            config.set(key, value)?;
+        }
+        Ok(config)
+    }
+
+    // sets a single option by its name in the config file, e.g. from a command line override
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let mut config = self.clone();
+        match key {
+            "hex_side" => config.hex_side = parse_value(key, value)?,
+            "radius" => config.radius = parse_value(key, value)?,
+            "holes_num" => config.holes_num = parse_value(key, value)?,
+            "holes_interval" => config.holes_interval = parse_value(key, value)?,
+            "tick_rate" => config.tick_rate = parse_value(key, value)?,
+            "speed_curve" => config.speed_curve = value.parse()?,
+            "speed_by" => config.speed_by = value.parse()?,
+            "level" => config.level = Some(PathBuf::from(value)),
+            "island" => config.island = value.parse()?,
+            "controls" => config.controls = value.parse()?,
+            "players" => config.players = parse_value(key, value)?,
+            "rivals" => config.rivals = parse_value(key, value)?,
+            "rival_difficulty" => config.rival_difficulty = value.parse()?,
+            _ => return Err(format!("unknown option: {}", key))
+        }
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // the outer ring is always holes and the snake starts three tiles long
+        if self.radius < 3 {
+            return Err(format!("radius must be at least 3, got {}", self.radius));
+        }
+        if self.holes_num < 0 {
+            return Err(format!("holes_num can't be negative, got {}", self.holes_num));
+        }
+        if self.holes_interval < 1 {
+            return Err(format!("holes_interval must be at least 1, got {}", self.holes_interval));
+        }
+        if self.tick_rate < 1 {
+            return Err(format!("tick_rate must be at least 1, got {}", self.tick_rate));
+        }
+        if !(1..=MAX_PLAYERS).contains(&self.players) {
+            return Err(format!("players must be between 1 and {}, got {}", MAX_PLAYERS, self.players));
+        }
+        if self.snakes() > MAX_PLAYERS {
+            return Err(format!("players and rivals can't be more than {} together, got {}", MAX_PLAYERS, self.snakes()));
+        }
+        // see Level::spawns
+        if self.snakes() > 1 && self.radius < 5 {
+            return Err(format!("more than one snake needs a radius of at least 5, got {}", self.radius));
+        }
+        if !self.hex_side.is_finite() || self.hex_side <= 0.0 {
+            return Err(format!("hex_side must be a positive number, got {}", self.hex_side));
+        }
+        Ok(())
+    }
+
+    // the players' snakes come first, then the rivals'
+    pub fn snakes(&self) -> usize {
+        self.players + self.rivals
+    }
+
+    pub fn board_settings(&self) -> BoardSettings {
+        BoardSettings {
+            radius: self.radius,
+            holes_num: self.holes_num,
+            holes_interval: self.holes_interval,
+            tick_rate: self.tick_rate,
+            speed_curve: self.speed_curve,
+            speed_by: self.speed_by,
+            level: self.level.as_ref().map(|path| path.display().to_string()),
+            island: self.island,
+            players: self.players,
+        }
+    }
+
+    // the level file if there is one, otherwise the configured island; either has to fit all the snakes
+    pub fn load_level(&self, seed: u64) -> io::Result<Level> {
+        let level = match &self.level {
+            Some(path) => Level::load(path)?,
+            None => self.island_level(seed)
+        };
+        level.spawns(self.snakes()).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", level.name, err)))?;
+        Ok(level)
+    }
+
+    // generated islands are different for every seed
+    pub fn island_level(&self, seed: u64) -> Level {
+        match self.island {
+            IslandShape::Hexagon => Level::hexagon(self.radius),
+            IslandShape::Generated => island::generate(seed, self.radius, self.snakes()),
+        }
+    }
+
+    pub fn tick_rate_at(&self, score: i32, length: usize) -> u32 {
+        let points = match self.speed_by {
+            SpeedBy::Score => score,
+            SpeedBy::Length => length as i32,
+        };
+        self.speed_curve.tick_rate(self.tick_rate, points)
+    }
+
+    // half of the window's width and height needed to fit a board of the given radius
+    pub fn screen_size(&self, radius: i32) -> f32 {
+        ((radius as f32) * 2.0 + 1.0) * 3_f32.sqrt() / 2_f32 * self.hex_side
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value for {}: {}", key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(GameConfig::parse("").unwrap(), GameConfig::default());
+
+        let config = GameConfig::parse("radius = 6\ntick_rate = 4\n").unwrap();
+        assert_eq!(config.radius, 6);
+        assert_eq!(config.tick_rate, 4);
+        assert_eq!(config.holes_num, GameConfig::default().holes_num);
+
+        assert!(GameConfig::parse("radius = 2").is_err());
+        assert!(GameConfig::parse("colour = \"red\"").is_err());
+        for hex_side in ["0.0", "-20.0", "nan", "inf"] {
+            assert!(GameConfig::parse(&format!("hex_side = {}", hex_side)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_set() {
+        let mut config = GameConfig::default();
+        config.set("holes_num", "5").unwrap();
+        assert_eq!(config.holes_num, 5);
+        assert!(config.set("holes_interval", "0").is_err());
+        assert!(config.set("radius", "big").is_err());
+        assert!(config.set("speed", "3").is_err());
+        config.set("speed_curve", "stepped:2:1:5").unwrap();
+        assert_eq!(config.speed_curve, SpeedCurve::Stepped { every: 2, step: 1, max_rate: 5 });
+        assert!(config.set("speed_curve", "stepped:0:1:5").is_err());
+        config.set("speed_by", "length").unwrap();
+        assert_eq!(config.speed_by, SpeedBy::Length);
+        config.set("island", "generated").unwrap();
+        assert_eq!(config.island, IslandShape::Generated);
+        assert!(config.set("island", "square").is_err());
+        config.set("controls", "absolute").unwrap();
+        assert_eq!(config.controls, ControlScheme::Absolute);
+        assert_eq!(config.controls.toggled(), ControlScheme::Relative);
+        config.set("players", "2").unwrap();
+        assert_eq!(config.players, 2);
+        assert!(config.set("players", "0").is_err());
+        assert!(config.set("players", "5").is_err());
+        config.set("rivals", "2").unwrap();
+        assert_eq!(config.snakes(), 4);
+        assert!(config.set("rivals", "3").is_err());
+        config.set("rival_difficulty", "hard").unwrap();
+        assert_eq!(config.rival_difficulty, Difficulty::Hard);
+        assert!(config.set("rival_difficulty", "evil").is_err());
+
+        let path = std::env::temp_dir().join(format!("snek-config-{}.toml", std::process::id()));
+        fs::write(&path, "radius = 5\nholes_num = 2\n").unwrap();
+        let overrides = [("holes_num".to_string(), "7".to_string())];
+        let config = GameConfig::load_with_overrides(Some(&path), &overrides).unwrap();
+        assert_eq!((config.radius, config.holes_num), (5, 7));
+        assert!(GameConfig::load_with_overrides(Some(&path), &[("radius".to_string(), "1".to_string())]).is_err());
+        let config = GameConfig::load_with_overrides_from(None, &overrides).unwrap();
+        assert_eq!((config.radius, config.holes_num), (GameConfig::default().radius, 7));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_speed_curve() {
+        for text in ["fixed", "linear:0.5:8", "stepped:3:2:9"] {
+            assert_eq!(text.parse::<SpeedCurve>().unwrap().to_string(), text);
+        }
+        assert!("linear:0.5".parse::<SpeedCurve>().is_err());
+        assert!("quadratic".parse::<SpeedCurve>().is_err());
+
+        assert_eq!(SpeedCurve::Fixed.tick_rate(2, 100), 2);
+        let linear = SpeedCurve::Linear { per_point: 0.5, max_rate: 5 };
+        assert_eq!(linear.tick_rate(2, 0), 2);
+        assert_eq!(linear.tick_rate(2, 3), 3);
+        assert_eq!(linear.tick_rate(2, 100), 5);
+        let stepped = SpeedCurve::Stepped { every: 3, step: 2, max_rate: 9 };
+        assert_eq!(stepped.tick_rate(2, 2), 2);
+        assert_eq!(stepped.tick_rate(2, 3), 4);
+        assert_eq!(stepped.tick_rate(2, 30), 9);
+
+        // huge curves run into the max rate instead of overflowing
+        let huge = SpeedCurve::Linear { per_point: 1e30, max_rate: 7 };
+        assert_eq!(huge.tick_rate(2, 1000), 7);
+        let huge = SpeedCurve::Stepped { every: 1, step: u32::MAX, max_rate: 7 };
+        assert_eq!(huge.tick_rate(2, i32::MAX), 7);
+        assert!("linear:inf:8".parse::<SpeedCurve>().is_err());
+        assert!("linear:NaN:8".parse::<SpeedCurve>().is_err());
+        assert!("linear:-1:8".parse::<SpeedCurve>().is_err());
+
+        let config = GameConfig::parse("speed_curve = \"linear:1:4\"\nspeed_by = \"length\"").unwrap();
+        assert_eq!(config.tick_rate_at(0, 1), 3);
+        assert_eq!(config.tick_rate_at(0, 10), 4);
+    }
+
+    #[test]
+    fn test_difficulty() {
+        let mut config = GameConfig::default();
+        "hard".parse::<Difficulty>().unwrap().apply(&mut config);
+        assert_eq!(config.tick_rate, 3);
+        assert!(config.tick_rate_at(30, 0) > config.tick_rate_at(0, 0));
+        assert!("impossible".parse::<Difficulty>().is_err());
+    }
+}
@@ -1,9 +1,9 @@
-use ggez::event::KeyCode;
-use ggez::mint::Point2;
-use ggez::winit::event::VirtualKeyCode;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
 
-
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Direction {
     West,
     NorthWest,
@@ -13,6 +13,16 @@ pub enum Direction {
     SouthWest,
 }
 
+// rotations are from the snake's perspective, Towards points the head in the given direction whichever way it's looking;
+// written as "L", "R" or the direction's name
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Turn {
+    Left,
+    Right,
+    Towards(Direction),
+}
+
 impl Direction {
     pub fn value(&self) -> i32 {
         match self {
@@ -37,30 +47,26 @@ impl Direction {
         }
     }
 
-    pub fn change_dir(from_dir: Direction, with_key: KeyCode) -> Option<Direction> {
-        let change_value = match with_key {
-            VirtualKeyCode::A => -1,
-            VirtualKeyCode::D => 1,
-            VirtualKeyCode::Left => -1,
-            VirtualKeyCode::Right => 1,
-            _ => return None
-        };
-        let new_value = (from_dir.value() + change_value).rem_euclid(6);
-        Direction::from_value(new_value)
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "West" => Some(Direction::West),
+            "NorthWest" => Some(Direction::NorthWest),
+            "NorthEast" => Some(Direction::NorthEast),
+            "East" => Some(Direction::East),
+            "SouthEast" => Some(Direction::SouthEast),
+            "SouthWest" => Some(Direction::SouthWest),
+            _ => None
+        }
     }
 
-    pub fn get_vertices(&self, side: f32) -> (Point2<f32>, Point2<f32>) {
-        let sin = 3_f32.sqrt() / 2_f32;
-        let vertices = [
-            Point2::from([-sin * side, side / 2_f32]),
-            Point2::from([-sin * side, -side / 2_f32]),
-            Point2::from([0.0, -side]),
-            Point2::from([sin * side, -side / 2_f32]),
-            Point2::from([sin * side, side / 2_f32]),
-            Point2::from([0.0, side])
-        ];
-        let val = self.value() as usize;
-        (vertices[val], vertices[(val + 1).rem_euclid(6)])
+    pub fn change_dir(from_dir: Direction, turn: Turn) -> Direction {
+        let change_value = match turn {
+            Turn::Left => -1,
+            Turn::Right => 1,
+            Turn::Towards(dir) => return dir,
+        };
+        let new_value = (from_dir.value() + change_value).rem_euclid(6);
+        Direction::from_value(new_value).unwrap()
     }
 
     pub fn opposite_direction(&self) -> Direction {
@@ -75,21 +81,60 @@ impl Direction {
     }
 }
 
+impl FromStr for Turn {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Turn, String> {
+        match text {
+            "L" => Ok(Turn::Left),
+            "R" => Ok(Turn::Right),
+            _ => Direction::from_name(text).map(Turn::Towards).ok_or(format!("invalid turn: {}", text))
+        }
+    }
+}
+
+impl fmt::Display for Turn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Turn::Left => write!(f, "L"),
+            Turn::Right => write!(f, "R"),
+            Turn::Towards(dir) => write!(f, "{:?}", dir),
+        }
+    }
+}
+
+impl TryFrom<String> for Turn {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Turn, String> {
+        text.parse()
+    }
+}
+
+impl From<Turn> for String {
+    fn from(turn: Turn) -> String {
+        turn.to_string()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_change_dir() {
-        if let Some(new_dir) = Direction::change_dir(Direction::East, KeyCode::A) {
-            assert_eq!(new_dir, Direction::NorthEast)
-        }
-        if let Some(new_dir) = Direction::change_dir(Direction::East, KeyCode::Right) {
-            assert_eq!(new_dir, Direction::SouthEast)
-        }
+        assert_eq!(Direction::change_dir(Direction::East, Turn::Left), Direction::NorthEast);
+        assert_eq!(Direction::change_dir(Direction::East, Turn::Right), Direction::SouthEast);
+        assert_eq!(Direction::change_dir(Direction::West, Turn::Left), Direction::SouthWest);
+        assert_eq!(Direction::change_dir(Direction::West, Turn::Towards(Direction::NorthEast)), Direction::NorthEast);
+    }
 
-        if let Some(new_dir) = Direction::change_dir(Direction::West, KeyCode::Left) {
-            assert_eq!(new_dir, Direction::SouthWest)
+    #[test]
+    fn test_turn_names() {
+        for turn in [Turn::Left, Turn::Right, Turn::Towards(Direction::SouthWest)] {
+            assert_eq!(turn.to_string().parse(), Ok(turn));
         }
+        assert_eq!("NorthEast".parse(), Ok(Turn::Towards(Direction::NorthEast)));
+        assert!("Up".parse::<Turn>().is_err());
     }
-}
\ No newline at end of file
+}
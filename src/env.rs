@@ -0,0 +1,265 @@
+use std::convert::TryFrom;
+use std::io;
+use crate::ai::{Bot, Controller};
+use crate::config::GameConfig;
+use crate::direction::Turn;
+use crate::game::{Death, GameState};
+use crate::hexagon::HexCoordinates;
+use crate::level::Level;
+
+// the agent's actions, relative to where the head is looking like the default controls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Straight,
+    Left,
+    Right,
+}
+
+impl Action {
+    pub const ALL: [Action; 3] = [Action::Straight, Action::Left, Action::Right];
+
+    pub fn turn(&self) -> Option<Turn> {
+        match self {
+            Action::Straight => None,
+            Action::Left => Some(Turn::Left),
+            Action::Right => Some(Turn::Right),
+        }
+    }
+}
+
+// the index a policy picks, 0 to go straight on, 1 to turn left and 2 to turn right
+impl TryFrom<usize> for Action {
+    type Error = String;
+
+    fn try_from(index: usize) -> Result<Action, String> {
+        Action::ALL.get(index).copied().ok_or_else(|| format!("no action {}, there are {}", index, Action::ALL.len()))
+    }
+}
+
+pub const CHANNELS: usize = 5;
+pub const LAND: usize = 0;
+pub const HOLE: usize = 1;
+pub const APPLE: usize = 2;
+// every snake's body, the agent's and the rivals'
+pub const BODY: usize = 3;
+pub const HEAD: usize = 4;
+
+// The board as CHANNELS planes of size x size cells, one cell per hex, holding 1.0 where the channel's thing
+// is and 0.0 elsewhere. A hex goes to column r + radius and row b + radius; the corners of the square that
+// don't make it onto the board are void and stay 0.0 in every channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub size: usize,
+    pub data: Vec<f32>,
+}
+
+impl Observation {
+    fn new(game: &GameState) -> Observation {
+        let radius = game.get_radius();
+        let size = 2 * radius as usize + 1;
+        let mut observation = Observation { size, data: vec![0.0; CHANNELS * size * size] };
+        for (coord, tile) in game.get_board() {
+            observation.set(if tile.is_hole() { HOLE } else { LAND }, coord, radius);
+        }
+        observation.set(APPLE, game.get_apple(), radius);
+        for snake in game.get_snakes() {
+            for part in snake.body() {
+                observation.set(BODY, &part.coordinates, radius);
+            }
+        }
+        let snake = game.get_snake();
+        if !snake.is_empty() {
+            observation.set(HEAD, snake.get_head(), radius);
+        }
+        observation
+    }
+
+    fn set(&mut self, channel: usize, coord: &HexCoordinates, radius: i32) {
+        if let Some(index) = self.index(channel, coord, radius) {
+            self.data[index] = 1.0;
+        }
+    }
+
+    fn index(&self, channel: usize, coord: &HexCoordinates, radius: i32) -> Option<usize> {
+        let (column, row) = (coord.get_r() + radius, coord.get_b() + radius);
+        let range = 0..self.size as i32;
+        if range.contains(&column) && range.contains(&row) {
+            Some((channel * self.size + row as usize) * self.size + column as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn shape(&self) -> [usize; 3] {
+        [CHANNELS, self.size, self.size]
+    }
+
+    pub fn get(&self, channel: usize, coord: &HexCoordinates) -> f32 {
+        let radius = (self.size / 2) as i32;
+        self.index(channel, coord, radius).map_or(0.0, |index| self.data[index])
+    }
+}
+
+// what happened to the agent on a single step, for the reward to be worked out from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Info {
+    pub apples: i32,
+    pub score: i32,
+    pub tick: u32,
+    pub death: Option<Death>,
+    // the game was cut short at max_ticks rather than lost
+    pub truncated: bool,
+}
+
+pub type Reward = Box<dyn Fn(&Info) -> f32>;
+
+// an apple is worth a point, dying costs one
+pub fn default_reward(info: &Info) -> f32 {
+    let death = if info.death.is_some() { -1.0 } else { 0.0 };
+    info.apples as f32 + death
+}
+
+// A gym-style environment: the agent plays the first snake, the rivals in the config play the others.
+pub struct Env {
+    config: GameConfig,
+    // a level file is read once, islands are made anew for every seed
+    level: Option<Level>,
+    game: GameState,
+    rivals: Vec<Box<dyn Controller>>,
+    reward: Reward,
+    max_ticks: u32,
+}
+
+impl Env {
+    pub fn new(config: &GameConfig, max_ticks: u32) -> io::Result<Env> {
+        let level = match &config.level {
+            Some(_) => Some(config.load_level(0)?),
+            None => None
+        };
+        Env::build(config, level, max_ticks)
+    }
+
+    // every game on the given level instead of the configured one
+    pub fn with_level(config: &GameConfig, level: Level, max_ticks: u32) -> io::Result<Env> {
+        level.spawns(config.snakes()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        Env::build(config, Some(level), max_ticks)
+    }
+
+    fn build(config: &GameConfig, level: Option<Level>, max_ticks: u32) -> io::Result<Env> {
+        if config.players != 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the environment has a single agent, use rivals for the other snakes"));
+        }
+        let mut env = Env {
+            config: config.clone(),
+            level,
+            game: GameState::new(config, 0),
+            rivals: Vec::new(),
+            reward: Box::new(default_reward),
+            max_ticks,
+        };
+        env.reset(0);
+        Ok(env)
+    }
+
+    pub fn set_reward(&mut self, reward: Reward) {
+        self.reward = reward;
+    }
+
+    pub fn get_game(&self) -> &GameState {
+        &self.game
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        let level = match &self.level {
+            Some(level) => level.clone(),
+            None => self.config.island_level(seed)
+        };
+        self.game = GameState::from_level(&self.config, &level, seed);
+        self.rivals = (0..self.config.rivals).map(|_| Bot::Rival(self.config.rival_difficulty).controller()).collect();
+        Observation::new(&self.game)
+    }
+
+    // stepping on after done only lets the tick run on
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool, Info) {
+        let mut turns = vec![action.turn().into_iter().collect::<Vec<Turn>>()];
+        for (index, rival) in self.rivals.iter_mut().enumerate() {
+            turns.push(rival.next_turns(&self.game, index + 1));
+        }
+        let score = self.game.get_score();
+        self.game.step_players(&turns);
+
+        let death = self.game.get_death(0);
+        let truncated = death.is_none() && !self.game.is_over() && self.game.get_tick() >= self.max_ticks;
+        let info = Info {
+            apples: self.game.get_score() - score,
+            score: self.game.get_score(),
+            tick: self.game.get_tick(),
+            death,
+            truncated,
+        };
+        let done = self.game.is_over() || death.is_some() || truncated;
+        (Observation::new(&self.game), (self.reward)(&info), done, info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observation() {
+        let config = GameConfig { radius: 5, ..GameConfig::default() };
+        let mut env = Env::new(&config, 100).unwrap();
+        let observation = env.reset(4);
+        assert_eq!(observation.shape(), [CHANNELS, 11, 11]);
+        assert_eq!(observation.data.len(), CHANNELS * 11 * 11);
+        let count = |channel: usize| observation.data[channel * 121..(channel + 1) * 121].iter().filter(|cell| **cell == 1.0).count();
+        // the 91 tiles of the board, the outer ring of 30 are holes
+        assert_eq!(count(LAND) + count(HOLE), 91);
+        assert_eq!(count(HOLE), 30);
+        assert_eq!((count(APPLE), count(BODY), count(HEAD)), (1, 3, 1));
+        let game = env.get_game();
+        assert_eq!(observation.get(HEAD, game.get_snake().get_head()), 1.0);
+        assert_eq!(observation.get(APPLE, game.get_apple()), 1.0);
+        assert_eq!(observation.get(LAND, &HexCoordinates::new(-5, 0, 5)), 0.0);
+        assert_eq!(env.reset(4), observation);
+    }
+
+    #[test]
+    fn test_step() {
+        let config = GameConfig { holes_num: 0, ..GameConfig::default() };
+        let level = Level::parse("direction: East\nmap:\n . . . . . .\n. . S @ . . .\n . . . . . .\n").unwrap();
+        let mut env = Env::with_level(&config, level, 12).unwrap();
+        let (observation, reward, done, info) = env.step(Action::Straight);
+        assert_eq!((reward, done, info.apples, info.score), (1.0, false, 1, 1));
+        assert_eq!(observation.get(HEAD, env.game.get_snake().get_head()), 1.0);
+
+        env.set_reward(Box::new(|info: &Info| if info.death.is_some() { -10.0 } else { 0.1 }));
+        let mut last = env.step(Action::Left);
+        while !last.2 {
+            last = env.step(Action::Straight);
+        }
+        let (_, reward, _, info) = last;
+        assert_eq!(info.death, Some(Death::Fall));
+        assert_eq!(reward, -10.0);
+        assert!(!info.truncated);
+
+        // circling round never ends on its own
+        let mut env = Env::new(&GameConfig::default(), 12).unwrap();
+        let mut last = env.step(Action::Left);
+        while !last.2 {
+            last = env.step(Action::Left);
+        }
+        assert!(last.3.truncated);
+        assert_eq!(last.3.tick, 12);
+        assert!(Env::new(&GameConfig { players: 2, ..GameConfig::default() }, 100).is_err());
+    }
+
+    #[test]
+    fn test_action() {
+        assert_eq!(Action::try_from(0), Ok(Action::Straight));
+        assert_eq!(Action::try_from(2), Ok(Action::Right));
+        assert!(Action::try_from(3).is_err());
+        assert!(Action::try_from(usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,561 @@
+use std::collections::VecDeque;
+use indexmap::map::IndexMap;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use crate::board::reachable_land;
+use crate::config::GameConfig;
+use crate::direction::{Direction, Turn};
+use crate::hexagon::HexCoordinates;
+use crate::level::Level;
+use crate::snake::{Snake, START_LENGTH};
+use crate::tile::Tile;
+
+// how many places add_holes tries for a single hole before it gives up on the rest
+const HOLE_ATTEMPTS: i32 = 60;
+
+// how a snake's game ended
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Death {
+    Fall,
+    SelfBite,
+    // into another snake
+    Collision,
+}
+
+impl Death {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Death::Fall => "fall",
+            Death::SelfBite => "self_bite",
+            Death::Collision => "collision",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Player {
+    snake: Snake,
+    score: i32,
+    // the apple being swallowed, the snake grows when its end gets there
+    prev_apple: Option<HexCoordinates>,
+    death: Option<Death>,
+}
+
+impl Player {
+    fn is_alive(&self) -> bool {
+        self.death.is_none()
+    }
+}
+
+// everything down to where the random numbers are, so a saved game goes on the way it would have
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameState {
+    config: GameConfig,
+    // in order, the random tiles are picked by their index
+    #[serde(with = "indexmap::serde_seq")]
+    board: IndexMap<HexCoordinates, Tile>,
+    radius: i32,
+    apple: HexCoordinates,
+    next_apples: VecDeque<HexCoordinates>,
+    players: Vec<Player>,
+    apples_eaten: i32,
+    end_game: bool,
+    tick: u32,
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl GameState {
+    pub fn new(config: &GameConfig, seed: u64) -> GameState {
+        GameState::from_level(config, &config.island_level(seed), seed)
+    }
+
+    // the level has to fit all the snakes, GameConfig::load_level checks that
+    pub fn from_level(config: &GameConfig, level: &Level, seed: u64) -> GameState {
+        let players = level.spawns(config.snakes()).expect("the level has no room for every player").into_iter()
+            .map(|(head, dir)| Player { snake: Snake::spawn(head, dir, START_LENGTH), score: 0, prev_apple: None, death: None })
+            .collect();
+        let mut game = GameState {
+            config: config.clone(),
+            apple: HexCoordinates::new(0, 0, 0),
+            board: level.board.clone(),
+            radius: level.radius(),
+            next_apples: level.apples.iter().cloned().collect(),
+            players,
+            apples_eaten: 0,
+            end_game: false,
+            tick: 0,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        };
+        game.apple = game.get_next_apple();
+        game
+    }
+
+    // a state read back from elsewhere has to hang together before it's played on, stepping
+    // indexes the players by the config and picks tiles out of the board
+    pub fn validate(&self) -> Result<(), String> {
+        self.config.validate()?;
+        if self.players.len() != self.config.snakes() {
+            return Err(format!("{} snakes for a config of {}", self.players.len(), self.config.snakes()));
+        }
+        let origin = HexCoordinates::origin();
+        if let Some(coord) = self.board.keys().find(|coord| coord.distance(&origin) > self.radius) {
+            return Err(format!("tile {:?} is outside the radius of {}", coord, self.radius));
+        }
+        if !self.board.values().any(|tile| !tile.is_hole()) {
+            return Err("the board has no land".to_string());
+        }
+        if let Some(apple) = std::iter::once(&self.apple).chain(&self.next_apples).find(|apple| !self.board.contains_key(*apple)) {
+            return Err(format!("apple {:?} is off the board", apple));
+        }
+        for (index, player) in self.players.iter().enumerate() {
+            if player.is_alive() && player.snake.is_empty() {
+                return Err(format!("snake {} is alive without a body", index));
+            }
+            // the head of a snake that fell off the edge is a step past it
+            let on_board = |coord: &HexCoordinates| self.board.contains_key(coord) || (!player.is_alive() && coord.distance(&origin) <= self.radius + 1);
+            if let Some(part) = player.snake.body().find(|part| !on_board(&part.coordinates)) {
+                return Err(format!("snake {} is off the board at {:?}", index, part.coordinates));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    pub fn get_board(&self) -> &IndexMap<HexCoordinates, Tile> {
+        &self.board
+    }
+
+    // the distance from the centre to the farthest tile
+    pub fn get_radius(&self) -> i32 {
+        self.radius
+    }
+
+    pub fn get_apple(&self) -> &HexCoordinates {
+        &self.apple
+    }
+
+    // the first player's snake
+    pub fn get_snake(&self) -> &Snake {
+        &self.players[0].snake
+    }
+
+    pub fn get_player_snake(&self, player: usize) -> &Snake {
+        &self.players[player].snake
+    }
+
+    pub fn get_snakes(&self) -> impl Iterator<Item = &Snake> {
+        self.players.iter().map(|player| &player.snake)
+    }
+
+    // the first player's score
+    pub fn get_score(&self) -> i32 {
+        self.players[0].score
+    }
+
+    pub fn get_scores(&self) -> Vec<i32> {
+        self.players.iter().map(|player| player.score).collect()
+    }
+
+    pub fn get_players(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_alive(&self, player: usize) -> bool {
+        self.players[player].is_alive()
+    }
+
+    pub fn get_death(&self, player: usize) -> Option<Death> {
+        self.players[player].death
+    }
+
+    // what a snake is called on the screen: the players by their names when there are any, or else by their numbers
+    pub fn snake_name(&self, names: &[String], snake: usize) -> String {
+        let players = self.config.players;
+        match names.get(snake).filter(|_| snake < players) {
+            Some(name) => name.to_uppercase(),
+            None if snake < players => format!("PLAYER {}", snake + 1),
+            None => format!("RIVAL {}", snake - players + 1)
+        }
+    }
+
+    // the last snake standing, or the best score if the last ones died together; None for a draw or a single player
+    pub fn get_winner(&self) -> Option<usize> {
+        if self.players.len() < 2 || !self.end_game {
+            return None;
+        }
+        if let Some(survivor) = self.players.iter().position(|player| player.is_alive()) {
+            return Some(survivor);
+        }
+        let best = self.players.iter().map(|player| player.score).max()?;
+        let mut best_players = (0..self.players.len()).filter(|player| self.players[*player].score == best);
+        match (best_players.next(), best_players.next()) {
+            (Some(winner), None) => Some(winner),
+            _ => None
+        }
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    // how many ticks per second the game should run at now, it can speed up as the snakes grow
+    pub fn get_tick_rate(&self) -> u32 {
+        let score = self.players.iter().map(|player| player.score).max().unwrap_or(0);
+        let length = self.players.iter().map(|player| player.snake.len()).max().unwrap_or(0);
+        self.config.tick_rate_at(score, length)
+    }
+
+    pub fn get_tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.end_game
+    }
+
+    // advances the game by one tick, applying the first player's turns made since the previous one
+    pub fn step(&mut self, turns: &[Turn]) {
+        self.step_players(&[turns.to_vec()]);
+    }
+
+    // the same for every player, turns[n] are the turns of player n
+    pub fn step_players(&mut self, turns: &[Vec<Turn>]) {
+        self.tick += 1;
+        for player in self.players.iter_mut().filter(|player| !player.is_alive() && player.snake.falling) {
+            player.snake.move_();
+        }
+        if self.end_game {
+            return;
+        }
+
+        for (index, player) in self.players.iter_mut().enumerate().filter(|(_, player)| player.is_alive()) {
+            for turn in turns.get(index).into_iter().flatten() {
+                player.snake.rotate_head(*turn);
+            }
+            player.snake.move_();
+        }
+        self.check_deaths();
+        let alive = self.players.iter().filter(|player| player.is_alive()).count();
+        if alive == 0 || (self.players.len() > 1 && alive == 1) {
+            self.end_game = true;
+        }
+        self.check_if_eaten_apple();
+    }
+
+    // all the snakes move at once, so who dies is decided only after every one of them has moved
+    fn check_deaths(&mut self) {
+        let mut deaths = Vec::new();
+        for (index, player) in self.players.iter().enumerate().filter(|(_, player)| player.is_alive()) {
+            let head = player.snake.get_head();
+            if player.snake.has_eaten_itself() {
+                deaths.push((index, Death::SelfBite));
+            } else if self.board.get(head).is_none_or(|tile| tile.is_hole()) {
+                deaths.push((index, Death::Fall));
+            } else if self.players.iter().enumerate().any(|(other_index, other)| other_index != index && other.snake.check_collision(head)) {
+                // into another snake's head or anywhere along its body, dead ones included
+                deaths.push((index, Death::Collision));
+            }
+        }
+        for (index, death) in deaths {
+            self.players[index].death = Some(death);
+            self.players[index].snake.falling = death == Death::Fall;
+        }
+    }
+
+    fn is_occupied(&self, coord: &HexCoordinates) -> bool {
+        self.players.iter().any(|player| player.snake.check_collision(coord))
+    }
+
+    // the level's own apples come first, as long as they can still be eaten
+    fn get_next_apple(&mut self) -> HexCoordinates {
+        while let Some(apple) = self.next_apples.pop_front() {
+            if matches!(self.board.get(&apple), Some(tile) if !tile.is_hole()) && !self.is_occupied(&apple) {
+                return apple;
+            }
+        }
+        self.get_random_tile()
+    }
+
+    fn get_random_tile(&mut self) -> HexCoordinates {
+        let board_size = self.board.len();
+        let mut rand_index = self.rng.gen_range(0..board_size);
+        let mut pair = self.board.get_index(rand_index).unwrap();
+        while pair.1.is_hole()  {
+            rand_index = self.rng.gen_range(0..board_size);
+            pair = self.board.get_index(rand_index).unwrap();
+        }
+        pair.0.clone()
+    }
+
+    fn check_if_eaten_apple(&mut self) {
+        for index in 0..self.players.len() {
+            if self.players[index].is_alive() && self.players[index].snake.get_head() == &self.apple {
+                self.players[index].prev_apple = Some(self.apple.clone());
+                self.apple = self.get_next_apple();
+                self.players[index].score += 1;
+                self.apples_eaten += 1;
+                if self.apples_eaten % self.config.holes_interval == 0 {
+                    self.add_holes();
+                }
+            }
+        }
+        for player in self.players.iter_mut().filter(|player| player.is_alive()) {
+            let end_coord = player.snake.get_end();
+            if player.prev_apple.as_ref() == Some(end_coord) {
+                player.snake.grow(end_coord.clone());
+                player.prev_apple = None;
+            }
+        }
+    }
+
+    // carves up to holes_num holes along a random walk, never splitting the land, cutting off the apple or
+    // leaving a head nowhere to go; gives up on the rest after HOLE_ATTEMPTS tries for one hole
+    fn add_holes(&mut self) {
+        if self.end_game {
+            return;
+        }
+        let mut reached = match self.players.iter().find(|player| player.is_alive()) {
+            Some(player) => reachable_land(&self.board, player.snake.get_head()).len(),
+            None => return
+        };
+        let mut wall_coord = self.get_random_tile();
+        for _ in 0..self.config.holes_num {
+            let mut placed = false;
+            for attempt in 1..=HOLE_ATTEMPTS {
+                let rand_index = self.rng.gen_range(0..6);
+                let rand_dir = Direction::from_value(rand_index).unwrap();
+                let rand_neighbour_coord = wall_coord.move_in_dir(rand_dir);
+                if self.try_hole(&rand_neighbour_coord, reached) {
+                    wall_coord = rand_neighbour_coord;
+                    reached -= 1;
+                    placed = true;
+                    break;
+                }
+                // the walk is stuck, carry on from somewhere else
+                if attempt % 6 == 0 {
+                    wall_coord = self.get_random_tile();
+                }
+            }
+            if !placed {
+                return;
+            }
+        }
+    }
+
+    // makes the tile a hole unless that cuts off any land the heads could reach, the apple included,
+    // or leaves a head nowhere to go
+    fn try_hole(&mut self, coord: &HexCoordinates, reached: usize) -> bool {
+        if !matches!(self.board.get(coord), Some(tile) if !tile.is_hole()) || coord == &self.apple || self.is_occupied(coord) {
+            return false;
+        }
+        self.board.get_mut(coord).unwrap().set_as_hole();
+        let alive: Vec<&Snake> = self.players.iter().filter(|player| player.is_alive()).map(|player| &player.snake).collect();
+        let has_way_out = alive.iter().all(|snake| {
+            let dir = snake.get_dir();
+            [dir, Direction::change_dir(dir, Turn::Left), Direction::change_dir(dir, Turn::Right)].iter()
+                .map(|dir| snake.get_head().move_in_dir(*dir))
+                .any(|next| matches!(self.board.get(&next), Some(tile) if !tile.is_hole()) && !self.is_occupied(&next))
+        });
+        let land = reachable_land(&self.board, alive[0].get_head());
+        if has_way_out && land.len() + 1 == reached && land.contains(&self.apple) {
+            return true;
+        }
+        self.board.insert(coord.clone(), Tile::new(false));
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let game = GameState::new(&GameConfig { rivals: 1, ..GameConfig::default() }, 3);
+        assert!(game.validate().is_ok());
+
+        let mut broken = game.clone();
+        broken.players.pop();
+        assert!(broken.validate().is_err());
+        let mut broken = game.clone();
+        broken.config.hex_side = -1.0;
+        assert!(broken.validate().is_err());
+        let mut broken = game.clone();
+        broken.apple = HexCoordinates::new(40, -40, 0);
+        assert!(broken.validate().is_err());
+        let mut broken = game;
+        broken.players[1].snake = Snake::spawn(HexCoordinates::new(0, 30, -30), Direction::East, START_LENGTH);
+        assert!(broken.validate().is_err());
+    }
+
+    #[test]
+    fn test_random_tile() {
+        let mut game = GameState::new(&GameConfig::default(), 0);
+        let random_tile_coord = game.get_random_tile();
+        let random_tile = game.board.get(&random_tile_coord).unwrap();
+        assert!(!random_tile.is_hole())
+    }
+
+    #[test]
+    fn test_add_walls(){
+        let config = GameConfig::default();
+        let mut game = GameState::new(&config, 0);
+        game.add_holes();
+        let mut counter = 0;
+        for (coord, tile) in game.board{
+            if tile.is_hole() &&  !(coord.get_r().abs() == config.radius || coord.get_b().abs() == config.radius || coord.get_g().abs() == config.radius){
+                counter += 1;
+            }
+        }
+        assert_eq!(counter, config.holes_num);
+    }
+
+    #[test]
+    fn test_holes_keep_land_connected() {
+        let config = GameConfig { holes_num: 6, ..GameConfig::default() };
+        for seed in 0..10 {
+            let mut game = GameState::new(&config, seed);
+            for _ in 0..8 {
+                game.add_holes();
+            }
+            let land = reachable_land(&game.board, game.players[0].snake.get_head());
+            assert_eq!(land.len(), crate::board::land_count(&game.board));
+            assert!(land.contains(&game.apple));
+        }
+
+        // there is hardly any room for holes on a thin strip, but add_holes still has to finish
+        let level = Level::parse("direction: East\nmap:\n. . S . . . @ .\n").unwrap();
+        let mut game = GameState::from_level(&config, &level, 0);
+        game.add_holes();
+        let land = reachable_land(&game.board, game.players[0].snake.get_head());
+        assert_eq!(land.len(), crate::board::land_count(&game.board));
+        assert!(!game.board.get(&game.players[0].snake.get_head().move_in_dir(Direction::East)).unwrap().is_hole());
+    }
+
+    #[test]
+    fn test_step() {
+        let mut game = GameState::new(&GameConfig::default(), 0);
+        game.apple = HexCoordinates::new(5, 0, -5);
+        game.step(&[]);
+        assert_eq!(game.players[0].snake.get_head(), &HexCoordinates::new(-1, 1, 0));
+
+        game.step(&[Turn::Right]);
+        assert_eq!(game.players[0].snake.get_head(), &HexCoordinates::new(-2, 1, 1));
+        assert!(!game.is_over());
+
+        for _ in 0..game.config.radius {
+            game.step(&[]);
+        }
+        assert!(game.is_over());
+        assert!(game.players[0].snake.falling);
+        assert_eq!(game.get_death(0), Some(Death::Fall));
+    }
+
+    #[test]
+    fn test_off_the_board() {
+        // a board without its outer ring of holes, the snake still falls off the edge
+        let mut game = GameState::new(&GameConfig::default(), 0);
+        let ahead = game.get_snake().get_head().move_in_dir(game.get_snake().get_dir());
+        game.board.shift_remove(&ahead);
+        game.step(&[]);
+        assert_eq!(game.get_death(0), Some(Death::Fall));
+    }
+
+    #[test]
+    fn test_board_radius() {
+        let config = GameConfig { radius: 5, ..GameConfig::default() };
+        let game = GameState::new(&config, 0);
+        assert_eq!(game.board.len(), 91);
+        assert_eq!(game.board.values().filter(|tile| tile.is_hole()).count(), 30);
+    }
+
+    #[test]
+    fn test_level_apples() {
+        let level = Level::parse("direction: East\nmap:\n . . . . .\n. . S @ @ .\n . . . . .\n").unwrap();
+        let mut game = GameState::from_level(&GameConfig::default(), &level, 0);
+        assert_eq!(game.get_radius(), 4);
+        assert_eq!(game.apple, level.apples[0]);
+        game.step(&[]);
+        assert_eq!(game.get_score(), 1);
+        assert_eq!(game.apple, level.apples[1]);
+        game.step(&[]);
+        assert_eq!(game.get_score(), 2);
+        assert!(!game.board.get(&game.apple).unwrap().is_hole());
+    }
+
+    #[test]
+    fn test_players() {
+        let config = GameConfig { players: 2, ..GameConfig::default() };
+        let mut game = GameState::new(&config, 0);
+        assert_eq!(game.get_players(), 2);
+        assert_eq!(game.get_snakes().count(), 2);
+        game.apple = HexCoordinates::new(0, 5, -5);
+        // the snakes start looking at each other across the centre and meet head to head
+        for _ in 0..3 {
+            game.step_players(&[vec![], vec![]]);
+            assert!(!game.is_over());
+        }
+        game.step_players(&[vec![], vec![]]);
+        assert!(game.is_over());
+        assert!(!game.is_alive(0) && !game.is_alive(1));
+        assert_eq!(game.get_death(0), Some(Death::Collision));
+        assert_eq!(game.get_winner(), None);
+
+        // the one that turns away survives and wins when the other one runs into its body
+        let mut game = GameState::new(&config, 0);
+        game.apple = HexCoordinates::new(0, 5, -5);
+        for _ in 0..3 {
+            game.step_players(&[vec![], vec![]]);
+        }
+        game.step_players(&[vec![Turn::Right], vec![]]);
+        assert!(!game.is_over());
+        game.step_players(&[vec![], vec![]]);
+        assert!(game.is_over());
+        assert!(game.is_alive(0));
+        assert_eq!(game.get_death(1), Some(Death::Collision));
+        assert_eq!(game.get_winner(), Some(0));
+
+        let mut game = GameState::new(&config, 0);
+        let apple = game.players[1].snake.get_head().move_in_dir(Direction::East);
+        game.apple = apple;
+        game.step_players(&[vec![], vec![]]);
+        assert_eq!(game.get_scores(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_snake_name() {
+        let game = GameState::new(&GameConfig { players: 2, rivals: 1, ..GameConfig::default() }, 0);
+        let names = ["edi".to_string(), "ann".to_string()];
+        assert_eq!(game.snake_name(&names, 1), "ANN");
+        assert_eq!(game.snake_name(&[], 1), "PLAYER 2");
+        assert_eq!(game.snake_name(&names, 2), "RIVAL 1");
+    }
+
+    #[test]
+    fn test_same_seed_same_game() {
+        let turns = [Turn::Left, Turn::Right];
+        let mut game1 = GameState::new(&GameConfig::default(), 42);
+        let mut game2 = GameState::new(&GameConfig::default(), 42);
+        assert_eq!(game1.apple, game2.apple);
+        for tick in 0..20 {
+            let turn = &turns[tick % 2..tick % 2 + 1];
+            game1.step(turn);
+            game2.step(turn);
+            if tick % 5 == 0 {
+                game1.add_holes();
+                game2.add_holes();
+            }
+            assert_eq!(game1.apple, game2.apple);
+        }
+        let holes1: Vec<&HexCoordinates> = game1.board.iter().filter(|(_, tile)| tile.is_hole()).map(|(coord, _)| coord).collect();
+        let holes2: Vec<&HexCoordinates> = game2.board.iter().filter(|(_, tile)| tile.is_hole()).map(|(coord, _)| coord).collect();
+        assert_eq!(holes1, holes2);
+    }
+}
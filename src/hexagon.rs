@@ -1,26 +1,78 @@
-use ggez::graphics::mint::Point2;
+use std::convert::TryFrom;
+use std::ops::{Add, Mul, Neg, Sub};
+use serde::{Deserialize, Serialize};
 use crate::direction::Direction;
 
 
-#[derive(Debug, Hash, Eq, PartialEq)]
+#[derive(Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Cube")]
 // r - NorthWest,  g - NorthEast, b - North
+// r + g + b is always 0, moving in a direction takes 1 from one of them and adds it to another
 pub struct HexCoordinates {
-    pub r: i32,
-    pub g: i32,
-    pub b: i32,
+    r: i32,
+    g: i32,
+    b: i32,
+}
+
+// the coordinates as they're read, before they're known to make a hex
+#[derive(Deserialize)]
+struct Cube {
+    r: i32,
+    g: i32,
+    b: i32,
+}
+
+impl TryFrom<Cube> for HexCoordinates {
+    type Error = String;
+
+    fn try_from(Cube { r, g, b }: Cube) -> Result<HexCoordinates, String> {
+        HexCoordinates::try_new(r, g, b).ok_or_else(|| format!("({}, {}, {}) is not a hex", r, g, b))
+    }
 }
 
 impl HexCoordinates {
     pub fn new(r: i32, g: i32, b: i32) -> HexCoordinates {
+        assert!(r + g + b == 0, "({}, {}, {}) is not a hex", r, g, b);
         HexCoordinates { r, g, b }
     }
 
+    pub fn try_new(r: i32, g: i32, b: i32) -> Option<HexCoordinates> {
+        if r + g + b == 0 {
+            Some(HexCoordinates { r, g, b })
+        } else {
+            None
+        }
+    }
+
+    pub fn origin() -> HexCoordinates {
+        HexCoordinates::new(0, 0, 0)
+    }
+
+    pub fn get_r(&self) -> i32 {
+        self.r
+    }
+
+    pub fn get_g(&self) -> i32 {
+        self.g
+    }
+
+    pub fn get_b(&self) -> i32 {
+        self.b
+    }
+
     pub fn get_eucl_center(&self, side: f32) -> (f32, f32) {
         let center_y = -(self.b as f32) * 1.5 * side;
         let center_x = (self.b as f32 / 2.0 + self.r as f32) * 3_f32.sqrt() * side;
         (center_x, center_y)
     }
 
+    // the hex a point is in, the inverse of get_eucl_center
+    pub fn from_pixel(x: f32, y: f32, side: f32) -> HexCoordinates {
+        let b = -y / (1.5 * side);
+        let r = x / (3_f32.sqrt() * side) - b / 2.0;
+        cube_round(r, -r - b, b)
+    }
+
     pub fn move_in_dir(&self, dir: Direction) -> HexCoordinates {
         match dir {
             Direction::West => HexCoordinates::new(self.r - 1, self.g + 1, self.b),
@@ -31,6 +83,85 @@ impl HexCoordinates {
             Direction::SouthWest => HexCoordinates::new(self.r, self.g + 1, self.b - 1)
         }
     }
+
+    // in the order of the directions' values, West first
+    pub fn neighbors(&self) -> [HexCoordinates; 6] {
+        [Direction::West, Direction::NorthWest, Direction::NorthEast, Direction::East, Direction::SouthEast, Direction::SouthWest]
+            .map(|dir| self.move_in_dir(dir))
+    }
+
+    // how many moves it takes to get from one hex to the other
+    pub fn distance(&self, other: &HexCoordinates) -> i32 {
+        ((self.r - other.r).abs() + (self.g - other.g).abs() + (self.b - other.b).abs()) / 2
+    }
+
+    // the hexes at the given distance, starting radius steps SouthEast and going around clockwise
+    pub fn ring(&self, radius: i32) -> Vec<HexCoordinates> {
+        if radius <= 0 {
+            return vec![self.clone()];
+        }
+        let mut hex = self.clone() + HexCoordinates::origin().move_in_dir(Direction::SouthEast) * radius;
+        let mut ring = Vec::new();
+        for value in 0..6 {
+            for _ in 0..radius {
+                ring.push(hex.clone());
+                hex = hex.move_in_dir(Direction::from_value(value).unwrap());
+            }
+        }
+        ring
+    }
+
+    // all the hexes up to the given distance, ring by ring from the centre out
+    pub fn spiral(&self, radius: i32) -> Vec<HexCoordinates> {
+        (0..radius + 1).flat_map(|ring| self.ring(ring)).collect()
+    }
+
+    // the hexes a straight line between the two centres crosses, both ends included
+    pub fn line_to(&self, other: &HexCoordinates) -> Vec<HexCoordinates> {
+        let steps = self.distance(other);
+        if steps == 0 {
+            return vec![self.clone()];
+        }
+        // nudged a bit so lines along the edges between hexes always pick the same side
+        let lerp = |from: i32, to: i32, step: i32, nudge: f32| {
+            from as f32 + nudge + (to - from) as f32 * step as f32 / steps as f32
+        };
+        (0..steps + 1)
+            .map(|step| cube_round(lerp(self.r, other.r, step, 1e-6), lerp(self.g, other.g, step, 1e-6), lerp(self.b, other.b, step, -2e-6)))
+            .collect()
+    }
+
+    // turned around the centre by 60 degrees per step, clockwise like Turn::Right for positive steps
+    pub fn rotate_around(&self, centre: &HexCoordinates, steps: i32) -> HexCoordinates {
+        let mut offset = self.clone() - centre.clone();
+        for _ in 0..steps.rem_euclid(6) {
+            offset = HexCoordinates::new(-offset.g, -offset.b, -offset.r);
+        }
+        offset + centre.clone()
+    }
+
+    // mirrored across the line through the centre going in the given direction
+    pub fn reflect_around(&self, centre: &HexCoordinates, axis: Direction) -> HexCoordinates {
+        let steps = axis.value() - Direction::East.value();
+        let offset = self.rotate_around(centre, -steps) - centre.clone();
+        let reflected = HexCoordinates::new(-offset.g, -offset.r, -offset.b);
+        (reflected + centre.clone()).rotate_around(centre, steps)
+    }
+}
+
+// the hex closest to a point given in fractional cube coordinates
+fn cube_round(r: f32, g: f32, b: f32) -> HexCoordinates {
+    let (mut rounded_r, mut rounded_g, mut rounded_b) = (r.round(), g.round(), b.round());
+    let (diff_r, diff_g, diff_b) = ((rounded_r - r).abs(), (rounded_g - g).abs(), (rounded_b - b).abs());
+    // the coordinate rounded the most is fixed to keep the sum at 0
+    if diff_r > diff_g && diff_r > diff_b {
+        rounded_r = -rounded_g - rounded_b;
+    } else if diff_g > diff_b {
+        rounded_g = -rounded_r - rounded_b;
+    } else {
+        rounded_b = -rounded_r - rounded_g;
+    }
+    HexCoordinates::new(rounded_r as i32, rounded_g as i32, rounded_b as i32)
 }
 
 impl Clone for HexCoordinates {
@@ -39,26 +170,134 @@ impl Clone for HexCoordinates {
     }
 }
 
-pub struct Hexagon {
-    pub center: Point2<f32>,
-    side: f32,
-    pub vertices: [Point2<f32>; 6],
+impl Add for HexCoordinates {
+    type Output = HexCoordinates;
+
+    fn add(self, other: HexCoordinates) -> HexCoordinates {
+        HexCoordinates::new(self.r + other.r, self.g + other.g, self.b + other.b)
+    }
+}
+
+impl Sub for HexCoordinates {
+    type Output = HexCoordinates;
+
+    fn sub(self, other: HexCoordinates) -> HexCoordinates {
+        HexCoordinates::new(self.r - other.r, self.g - other.g, self.b - other.b)
+    }
 }
 
-impl Hexagon {
-    pub fn new(coord: HexCoordinates, side: f32) -> Hexagon {
-        let (center_x, center_y) = coord.get_eucl_center(side);
-
-        let center = Point2::from([center_x, center_y]);
-        let sin = 3_f32.sqrt() / 2_f32;
-        let vertices = [
-            Point2::from([center_x, center_y + side]),
-            Point2::from([center_x + sin * side, center_y + side / 2_f32]),
-            Point2::from([center_x + sin * side, center_y - side / 2_f32]),
-            Point2::from([center_x, center_y - side]),
-            Point2::from([center_x - sin * side, center_y - side / 2_f32]),
-            Point2::from([center_x - sin * side, center_y + side / 2_f32])
-        ];
-        Hexagon { center, side, vertices }
+impl Neg for HexCoordinates {
+    type Output = HexCoordinates;
+
+    fn neg(self) -> HexCoordinates {
+        HexCoordinates::new(-self.r, -self.g, -self.b)
+    }
+}
+
+impl Mul<i32> for HexCoordinates {
+    type Output = HexCoordinates;
+
+    fn mul(self, factor: i32) -> HexCoordinates {
+        HexCoordinates::new(self.r * factor, self.g * factor, self.b * factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(r: i32, g: i32, b: i32) -> HexCoordinates {
+        HexCoordinates::new(r, g, b)
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert_eq!(HexCoordinates::try_new(1, -3, 2), Some(hex(1, -3, 2)));
+        assert_eq!(HexCoordinates::try_new(1, 1, 1), None);
+        assert_eq!(serde_json::from_str::<HexCoordinates>("{\"r\": 1, \"g\": -3, \"b\": 2}").unwrap(), hex(1, -3, 2));
+        assert!(serde_json::from_str::<HexCoordinates>("{\"r\": 1, \"g\": 1, \"b\": 1}").is_err());
+        assert_eq!(serde_json::to_string(&hex(1, -3, 2)).unwrap(), "{\"r\":1,\"g\":-3,\"b\":2}");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_new_not_a_hex() {
+        hex(1, 1, 1);
+    }
+
+    #[test]
+    fn test_ops() {
+        assert_eq!(hex(1, -3, 2) + hex(-1, 0, 1), hex(0, -3, 3));
+        assert_eq!(hex(1, -3, 2) - hex(-1, 0, 1), hex(2, -3, 1));
+        assert_eq!(-hex(1, -3, 2), hex(-1, 3, -2));
+        assert_eq!(hex(1, -3, 2) * 3, hex(3, -9, 6));
+    }
+
+    #[test]
+    fn test_neighbors_and_distance() {
+        let centre = hex(2, -1, -1);
+        let neighbors = centre.neighbors();
+        assert_eq!(neighbors[0], centre.move_in_dir(Direction::West));
+        assert_eq!(neighbors[5], centre.move_in_dir(Direction::SouthWest));
+        assert!(neighbors.iter().all(|neighbor| neighbor.distance(&centre) == 1));
+        assert_eq!(hex(0, 0, 0).distance(&hex(3, -5, 2)), 5);
+        assert_eq!(hex(3, -5, 2).distance(&hex(3, -5, 2)), 0);
+    }
+
+    #[test]
+    fn test_ring_and_spiral() {
+        let centre = hex(1, 1, -2);
+        assert_eq!(centre.ring(0), vec![centre.clone()]);
+        for radius in 1..5 {
+            let ring = centre.ring(radius);
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert!(ring.iter().all(|hex| hex.distance(&centre) == radius));
+            // every hex is next to the one before it
+            assert!(ring.windows(2).all(|pair| pair[0].distance(&pair[1]) == 1));
+        }
+        let spiral = centre.spiral(3);
+        assert_eq!(spiral.len(), 37);
+        assert_eq!(spiral[0], centre);
+        assert_eq!(spiral.iter().collect::<std::collections::HashSet<_>>().len(), 37);
+    }
+
+    #[test]
+    fn test_line_to() {
+        let from = hex(0, 0, 0);
+        assert_eq!(from.line_to(&from), vec![from.clone()]);
+        assert_eq!(from.line_to(&hex(3, -3, 0)), vec![hex(0, 0, 0), hex(1, -1, 0), hex(2, -2, 0), hex(3, -3, 0)]);
+        let to = hex(4, -1, -3);
+        let line = from.line_to(&to);
+        assert_eq!(line.len(), 5);
+        assert_eq!(line.first(), Some(&from));
+        assert_eq!(line.last(), Some(&to));
+        assert!(line.windows(2).all(|pair| pair[0].distance(&pair[1]) == 1));
+    }
+
+    #[test]
+    fn test_rotate_and_reflect() {
+        let centre = hex(1, -1, 0);
+        let west = centre.move_in_dir(Direction::West);
+        assert_eq!(west.rotate_around(&centre, 1), centre.move_in_dir(Direction::NorthWest));
+        assert_eq!(west.rotate_around(&centre, -1), centre.move_in_dir(Direction::SouthWest));
+        assert_eq!(west.rotate_around(&centre, 6), west);
+        let far = hex(3, -5, 2);
+        assert_eq!(far.rotate_around(&centre, 3), centre.clone() - (far.clone() - centre.clone()));
+        assert_eq!(far.rotate_around(&centre, 2).distance(&centre), far.distance(&centre));
+
+        let north_west = centre.move_in_dir(Direction::NorthWest);
+        assert_eq!(north_west.reflect_around(&centre, Direction::East), centre.move_in_dir(Direction::SouthWest));
+        assert_eq!(north_west.reflect_around(&centre, Direction::NorthWest), north_west);
+        assert_eq!(north_west.reflect_around(&centre, Direction::NorthEast), centre.move_in_dir(Direction::East));
+        assert_eq!(far.reflect_around(&centre, Direction::SouthEast).reflect_around(&centre, Direction::SouthEast), far);
+    }
+
+    #[test]
+    fn test_from_pixel() {
+        for hex in hex(0, 0, 0).spiral(4) {
+            let (x, y) = hex.get_eucl_center(30.0);
+            assert_eq!(HexCoordinates::from_pixel(x, y, 30.0), hex);
+            assert_eq!(HexCoordinates::from_pixel(x + 10.0, y - 12.0, 30.0), hex);
+        }
     }
 }
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::config::{IslandShape, SpeedBy, SpeedCurve};
+use crate::game::GameState;
+
+pub const TABLE_SIZE: usize = 10;
+
+// the settings a score was made with; scores are only compared against the same settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardSettings {
+    pub radius: i32,
+    pub holes_num: i32,
+    pub holes_interval: i32,
+    pub tick_rate: u32,
+    #[serde(default)]
+    pub speed_curve: SpeedCurve,
+    #[serde(default)]
+    pub speed_by: SpeedBy,
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub island: IslandShape,
+    #[serde(default = "one_player")]
+    pub players: usize,
+}
+
+fn one_player() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub score: i32,
+    pub length: usize,
+    pub duration_secs: u64,
+    pub seed: u64,
+}
+
+impl ScoreEntry {
+    // the first snake's, from a game played alone
+    pub fn of(game: &GameState, name: &str, duration_secs: u64) -> ScoreEntry {
+        ScoreEntry {
+            name: name.to_string(),
+            score: game.get_score(),
+            length: game.get_snake().len(),
+            duration_secs,
+            seed: game.get_seed(),
+        }
+    }
+}
+
+// the name scores go under without one given, the user's login
+pub fn default_name() -> String {
+    std::env::var("USER").unwrap_or_else(|_| "player".to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreTable {
+    pub settings: BoardSettings,
+    pub entries: Vec<ScoreEntry>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HighScores {
+    tables: Vec<ScoreTable>,
+}
+
+impl HighScores {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("snek").join("highscores.json"))
+    }
+
+    // a missing file is just an empty table
+    pub fn load(path: &Path) -> io::Result<HighScores> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HighScores::default()),
+            Err(err) => Err(err)
+        }
+    }
+
+    // the player's own, empty without a data directory to keep them in
+    pub fn load_default() -> io::Result<HighScores> {
+        HighScores::default_path().map_or_else(|| Ok(HighScores::default()), |path| HighScores::load(&path))
+    }
+
+    // to where load_default finds them
+    pub fn save_default(&self) -> io::Result<()> {
+        HighScores::default_path().map_or(Ok(()), |path| self.save(&path))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+
+    pub fn table(&self, settings: &BoardSettings) -> &[ScoreEntry] {
+        match self.tables.iter().find(|table| &table.settings == settings) {
+            Some(table) => &table.entries,
+            None => &[]
+        }
+    }
+
+    // returns the entry's place in the table, or None if it didn't make it
+    pub fn submit(&mut self, settings: &BoardSettings, entry: ScoreEntry) -> Option<usize> {
+        let index = match self.tables.iter().position(|table| &table.settings == settings) {
+            Some(index) => index,
+            None => {
+                self.tables.push(ScoreTable { settings: settings.clone(), entries: Vec::new() });
+                self.tables.len() - 1
+            }
+        };
+        let entries = &mut self.tables[index].entries;
+        let place = entries.iter()
+            .position(|other| (entry.score, entry.length) > (other.score, other.length))
+            .unwrap_or(entries.len());
+        if place >= TABLE_SIZE {
+            return None;
+        }
+        entries.insert(place, entry);
+        entries.truncate(TABLE_SIZE);
+        Some(place)
+    }
+
+    // the game that just ended, under the settings it was played with
+    pub fn submit_game(&mut self, game: &GameState, name: &str, duration_secs: u64) -> Option<usize> {
+        self.submit(&game.get_config().board_settings(), ScoreEntry::of(game, name, duration_secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(radius: i32) -> BoardSettings {
+        BoardSettings { radius, holes_num: 3, holes_interval: 3, tick_rate: 2, speed_curve: SpeedCurve::Fixed, speed_by: SpeedBy::Score, level: None, island: IslandShape::Hexagon, players: 1 }
+    }
+
+    fn entry(name: &str, score: i32) -> ScoreEntry {
+        ScoreEntry { name: name.to_string(), score, length: score as usize + 3, duration_secs: 10, seed: 1 }
+    }
+
+    #[test]
+    fn test_submit() {
+        let mut scores = HighScores::default();
+        assert_eq!(scores.submit(&settings(9), entry("a", 5)), Some(0));
+        assert_eq!(scores.submit(&settings(9), entry("b", 7)), Some(0));
+        assert_eq!(scores.submit(&settings(9), entry("c", 6)), Some(1));
+        assert_eq!(scores.submit(&settings(5), entry("d", 1)), Some(0));
+
+        let names: Vec<&str> = scores.table(&settings(9)).iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "c", "a"]);
+        assert_eq!(scores.table(&settings(5)).len(), 1);
+        assert!(scores.table(&settings(7)).is_empty());
+
+        for score in 10..10 + TABLE_SIZE as i32 {
+            scores.submit(&settings(9), entry("e", score));
+        }
+        assert_eq!(scores.table(&settings(9)).len(), TABLE_SIZE);
+        assert_eq!(scores.submit(&settings(9), entry("f", 0)), None);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("snek-highscores-{}.json", std::process::id()));
+        let mut scores = HighScores::default();
+        scores.submit(&settings(9), entry("a", 5));
+        scores.save(&path).unwrap();
+        assert_eq!(HighScores::load(&path).unwrap(), scores);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(HighScores::load(&path).unwrap(), HighScores::default());
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use crate::direction::Turn;
+
+// how many turns can wait for their tick, more key presses than that are dropped
+pub const QUEUE_SIZE: usize = 3;
+
+// turns made between ticks, one of them is applied on every tick so none is lost to a quicker one after it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputQueue {
+    turns: VecDeque<Turn>,
+}
+
+impl InputQueue {
+    pub fn new() -> InputQueue {
+        InputQueue::default()
+    }
+
+    // returns false if the queue is full and the turn was dropped
+    pub fn push(&mut self, turn: Turn) -> bool {
+        if self.turns.len() >= QUEUE_SIZE {
+            return false;
+        }
+        self.turns.push_back(turn);
+        true
+    }
+
+    // the turns for the next tick
+    pub fn next_turns(&mut self) -> Vec<Turn> {
+        self.turns.pop_front().into_iter().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Turn> {
+        self.turns.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.turns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.turns.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::direction::Direction;
+    use crate::game::GameState;
+
+    #[test]
+    fn test_queue() {
+        let mut queue = InputQueue::new();
+        assert!(queue.next_turns().is_empty());
+        for _ in 0..QUEUE_SIZE {
+            assert!(queue.push(Turn::Left));
+        }
+        assert!(!queue.push(Turn::Right));
+        assert_eq!(queue.len(), QUEUE_SIZE);
+        assert_eq!(queue.next_turns(), vec![Turn::Left]);
+        assert!(queue.push(Turn::Right));
+        assert_eq!(queue.iter().last(), Some(&Turn::Right));
+        queue.clear();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_turn_per_tick() {
+        // two quick lefts make a U-turn over two ticks instead of one sharp turn
+        let mut game = GameState::new(&GameConfig::default(), 0);
+        let mut queue = InputQueue::new();
+        queue.push(Turn::Left);
+        queue.push(Turn::Left);
+        game.step(&queue.next_turns());
+        assert_eq!(game.get_snake().get_dir(), Direction::SouthWest);
+        game.step(&queue.next_turns());
+        assert_eq!(game.get_snake().get_dir(), Direction::SouthEast);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use crate::board::{land_count, reachable_land};
+use crate::hexagon::HexCoordinates;
+use crate::level::Level;
+use crate::snake::START_LENGTH;
+use crate::tile::Tile;
+
+// how many points around the island the coastline is pulled in or out at
+const COAST_POINTS: i32 = 12;
+// the coastline moves by up to this many thousandths of a tile from tile to tile
+const COAST_JITTER: i32 = 400;
+const SMOOTHING_PASSES: usize = 2;
+// percentages of the hexagon's land a generated island must have, and of its own land the spawn must reach
+const MIN_AREA: usize = 40;
+const MIN_REACH: usize = 90;
+const MAX_ATTEMPTS: u64 = 20;
+
+// An organic island within the hexagon of the given radius, the same for the same seed.
+// The coastline's distance from the centre is drawn at a few points around it and blended between them,
+// then smoothed; only integers are used so that every machine grows the same island.
+// With more players every snake gets a strip of land from its tail to the centre.
+pub fn generate(seed: u64, radius: i32, players: usize) -> Level {
+    for attempt in 0..MAX_ATTEMPTS {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(attempt);
+        if let Some(level) = try_generate(&mut rng, radius, players) {
+            return Level { name: format!("island {}", seed), ..level };
+        }
+    }
+    Level::hexagon(radius)
+}
+
+fn try_generate(rng: &mut ChaCha8Rng, radius: i32, players: usize) -> Option<Level> {
+    let mut level = Level::hexagon(radius);
+    let coast: Vec<i32> = (0..COAST_POINTS).map(|_| rng.gen_range(radius * 450..(radius - 1) * 1000)).collect();
+
+    let coords: Vec<HexCoordinates> = level.board.keys().cloned().collect();
+    for coord in &coords {
+        let distance = distance_from_centre(coord);
+        if distance == radius {
+            continue;
+        }
+        // where around the island the tile is, in thousandths of the gap between two coast points
+        let around = ring_index(coord) * COAST_POINTS * 1000 / (6 * distance).max(1);
+        let (point, blend) = ((around / 1000) as usize, around % 1000);
+        let coastline = (coast[point % coast.len()] * (1000 - blend) + coast[(point + 1) % coast.len()] * blend) / 1000;
+        let jitter = rng.gen_range(-COAST_JITTER..COAST_JITTER + 1);
+        let is_hole = distance * 1000 > coastline + jitter;
+        level.board.insert(coord.clone(), Tile::new(is_hole));
+    }
+
+    for _ in 0..SMOOTHING_PASSES {
+        let mut smoothed = level.board.clone();
+        for coord in &coords {
+            if distance_from_centre(coord) == radius {
+                continue;
+            }
+            let land = coord.neighbors().iter()
+                .chain(std::iter::once(coord))
+                .filter(|neighbour| matches!(level.board.get(*neighbour), Some(tile) if !tile.is_hole()))
+                .count();
+            smoothed.insert(coord.clone(), Tile::new(land < 4));
+        }
+        level.board = smoothed;
+    }
+
+    // the snakes, the tiles ahead of them and behind their tails are always land, short of the outer ring
+    let spawns = Level::hexagon(radius).spawns(players).unwrap_or_default();
+    for (head, dir) in spawns {
+        let mut spawn = head.move_in_dir(dir);
+        for _ in 0..START_LENGTH + 2 {
+            if distance_from_centre(&spawn) < radius {
+                level.board.insert(spawn.clone(), Tile::new(false));
+            }
+            spawn = spawn.move_in_dir(dir.opposite_direction());
+        }
+        if players > 1 {
+            for coord in head.line_to(&level.start) {
+                level.board.insert(coord, Tile::new(false));
+            }
+        }
+    }
+
+    let total = land_count(&level.board);
+    let reached = reachable_land(&level.board, &level.start);
+    let hexagon_land = (3 * (radius - 1) * radius + 1) as usize;
+    if reached.len() * 100 < total * MIN_REACH || reached.len() * 100 < hexagon_land * MIN_AREA {
+        return None;
+    }
+    for (coord, tile) in level.board.iter_mut() {
+        if !reached.contains(coord) {
+            tile.set_as_hole();
+        }
+    }
+    Some(level)
+}
+
+fn distance_from_centre(coord: &HexCoordinates) -> i32 {
+    coord.distance(&HexCoordinates::origin())
+}
+
+// the tile's place on its ring around the centre, from 0 to 6 * distance - 1
+fn ring_index(coord: &HexCoordinates) -> i32 {
+    let ring = HexCoordinates::origin().ring(distance_from_centre(coord));
+    ring.iter().position(|hex| hex == coord).unwrap_or(0) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        for seed in 0..20 {
+            let level = generate(seed, 9, 1);
+            assert!(level.validate().is_ok());
+            let land = land_count(&level.board);
+            assert_eq!(reachable_land(&level.board, &level.start).len(), land);
+            assert!(land * 100 >= (3 * 8 * 9 + 1) * MIN_AREA);
+            assert_eq!(level.board.len(), Level::hexagon(9).board.len());
+            assert!(level.board.iter().all(|(coord, tile)| tile.is_hole() || distance_from_centre(coord) < 9));
+        }
+        assert_eq!(generate(5, 9, 1), generate(5, 9, 1));
+        assert_ne!(generate(5, 9, 1).board, generate(6, 9, 1).board);
+        for seed in 0..10 {
+            assert!(generate(seed, 9, 3).spawns(3).is_ok());
+        }
+        // the spawn strip reaches the outer ring on the smallest islands, which has to stay holes
+        for seed in 0..50 {
+            let level = generate(seed, 3, 1);
+            assert!(level.validate().is_ok());
+            assert!(level.board.iter().all(|(coord, tile)| tile.is_hole() || distance_from_centre(coord) < 3));
+        }
+    }
+
+    #[test]
+    fn test_ring_index() {
+        let mut indexes: Vec<i32> = Level::hexagon(3).board.keys()
+            .filter(|coord| distance_from_centre(coord) == 2)
+            .map(ring_index)
+            .collect();
+        indexes.sort();
+        assert_eq!(indexes, (0..12).collect::<Vec<i32>>());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use indexmap::map::IndexMap;
+use crate::direction::Direction;
+use crate::hexagon::HexCoordinates;
+use crate::snake::START_LENGTH;
+use crate::tile::Tile;
+
+// A level is a few "key: value" lines followed by "map:" and the island drawn in offset rows,
+// every other tile shifted by one character like this:
+//
+//   name: Lake
+//   direction: West
+//   map:
+//    . . . .
+//   . . ~ . .
+//    . S . @
+//
+// '.' is land, '~' is a hole, 'S' is land with the snake's head on it and '@' is land with an apple.
+// The apples are eaten in the order they appear, top to bottom, before random ones come.
+// Everything around the drawn land is holes, and the map is moved so its middle is the board's centre.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub name: String,
+    pub board: IndexMap<HexCoordinates, Tile>,
+    pub start: HexCoordinates,
+    pub direction: Direction,
+    pub apples: Vec<HexCoordinates>,
+}
+
+impl Level {
+    // the full hexagon with its outer ring as holes
+    pub fn hexagon(grid_radius: i32) -> Level {
+        let mut board = IndexMap::new();
+        for radius in 0_i32..grid_radius + 1 {
+            for r in -radius..radius + 1 {
+                for b in -radius..radius + 1 {
+                    for g in -radius..radius + 1 {
+                        if (r.abs() + b.abs() + g.abs() == 2 * radius) && (r + b + g == 0) {
+                            let is_hole = r.abs() == grid_radius || b.abs() == grid_radius || g.abs() == grid_radius;
+                            board.insert(HexCoordinates::new(r, b, g), Tile::new(is_hole));
+                        }
+                    }
+                }
+            }
+        }
+        Level {
+            name: format!("hexagon {}", grid_radius),
+            board,
+            start: HexCoordinates::new(0, 0, 0),
+            direction: Direction::West,
+            apples: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Level> {
+        let text = fs::read_to_string(path)?;
+        Level::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+    }
+
+    pub fn parse(text: &str) -> Result<Level, String> {
+        let mut name = String::from("unnamed");
+        let mut direction = Direction::West;
+        let mut lines = text.lines().enumerate();
+        let mut has_map = false;
+        for (number, line) in lines.by_ref() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "map:" {
+                has_map = true;
+                break;
+            }
+            let (key, value) = line.split_once(':').ok_or(format!("line {}: expected 'key: value' or 'map:'", number + 1))?;
+            match key.trim() {
+                "name" => name = value.trim().to_string(),
+                "direction" => {
+                    direction = Direction::from_name(value.trim())
+                        .ok_or(format!("line {}: unknown direction '{}'", number + 1, value.trim()))?;
+                }
+                key => return Err(format!("line {}: unknown key '{}'", number + 1, key))
+            }
+        }
+        if !has_map {
+            return Err("missing 'map:' line".to_string());
+        }
+
+        // (row, doubled column, tile char) of every drawn tile
+        let mut cells = Vec::new();
+        let mut parity = None;
+        for (row, (number, line)) in lines.enumerate() {
+            for (column, symbol) in line.chars().enumerate() {
+                if symbol == ' ' {
+                    continue;
+                }
+                if !".~S@".contains(symbol) {
+                    return Err(format!("line {}: unknown tile '{}'", number + 1, symbol));
+                }
+                if *parity.get_or_insert((row + column) % 2) != (row + column) % 2 {
+                    return Err(format!("line {}: tile '{}' at column {} is not aligned with the rows around it", number + 1, symbol, column + 1));
+                }
+                cells.push((row as i32, column as i32, symbol));
+            }
+        }
+        if cells.is_empty() {
+            return Err("the map is empty".to_string());
+        }
+
+        let min_row = cells.iter().map(|cell| cell.0).min().unwrap();
+        let max_row = cells.iter().map(|cell| cell.0).max().unwrap();
+        let min_column = cells.iter().map(|cell| cell.1).min().unwrap();
+        let max_column = cells.iter().map(|cell| cell.1).max().unwrap();
+        let middle_row = (min_row + max_row) / 2;
+        let mut middle_column = (min_column + max_column) / 2;
+        if Some(((middle_row + middle_column) % 2) as usize) != parity {
+            middle_column += 1;
+        }
+        let to_hex = |row: i32, column: i32| {
+            let b = middle_row - row;
+            let r = (column - middle_column + row - middle_row) / 2;
+            HexCoordinates::new(r, -r - b, b)
+        };
+
+        let mut board = IndexMap::new();
+        let mut start = None;
+        let mut apples = Vec::new();
+        for (row, column, symbol) in cells {
+            let coord = to_hex(row, column);
+            match symbol {
+                'S' if start.is_some() => return Err("the map has more than one 'S'".to_string()),
+                'S' => start = Some(coord.clone()),
+                '@' => apples.push(coord.clone()),
+                _ => {}
+            }
+            board.insert(coord, Tile::new(symbol == '~'));
+        }
+        let start = start.ok_or("the map has no 'S' for the snake")?;
+
+        let land: Vec<HexCoordinates> = board.iter().filter(|(_, tile)| !tile.is_hole()).map(|(coord, _)| coord.clone()).collect();
+        for coord in land {
+            for neighbour in coord.neighbors() {
+                board.entry(neighbour).or_insert_with(|| Tile::new(true));
+            }
+        }
+
+        let level = Level { name, board, start, direction, apples };
+        level.validate()?;
+        Ok(level)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let mut body = self.start.clone();
+        for _ in 0..START_LENGTH {
+            match self.board.get(&body) {
+                Some(tile) if !tile.is_hole() => {}
+                _ => return Err(format!("the snake doesn't fit on land behind 'S' looking {:?}", self.direction))
+            }
+            if self.apples.contains(&body) {
+                return Err("an apple is under the snake".to_string());
+            }
+            body = body.move_in_dir(self.direction.opposite_direction());
+        }
+        let ahead = self.start.move_in_dir(self.direction);
+        if !matches!(self.board.get(&ahead), Some(tile) if !tile.is_hole()) {
+            return Err(format!("the snake would fall right away looking {:?}", self.direction));
+        }
+        Ok(())
+    }
+
+    // where the snakes start; a single one starts on 'S', more are spread evenly around it,
+    // every one as far from 'S' as the level allows and looking towards it
+    pub fn spawns(&self, players: usize) -> Result<Vec<(HexCoordinates, Direction)>, String> {
+        if players <= 1 {
+            return Ok(vec![(self.start.clone(), self.direction)]);
+        }
+        let back = HexCoordinates::origin().move_in_dir(self.direction.opposite_direction());
+        for distance in (1..self.radius() / 2 + 1).rev() {
+            let spawns: Vec<(HexCoordinates, Direction)> = (0..players)
+                .map(|player| {
+                    let steps = (player * 6 / players) as i32;
+                    let head = (self.start.clone() + back.clone() * distance).rotate_around(&self.start, steps);
+                    (head, Direction::from_value((self.direction.value() + steps) % 6).unwrap())
+                })
+                .collect();
+            if self.spawns_fit(&spawns) {
+                return Ok(spawns);
+            }
+        }
+        Err(format!("there's no room for {} snakes around 'S'", players))
+    }
+
+    // every snake and the tile ahead of it on land of its own, with no apples under the snakes
+    fn spawns_fit(&self, spawns: &[(HexCoordinates, Direction)]) -> bool {
+        let mut taken = HashSet::new();
+        for (head, dir) in spawns {
+            let mut body = head.clone();
+            for _ in 0..START_LENGTH {
+                if self.apples.contains(&body) {
+                    return false;
+                }
+                taken.insert(body.clone());
+                body = body.move_in_dir(dir.opposite_direction());
+            }
+        }
+        if taken.len() != spawns.len() * START_LENGTH {
+            return false;
+        }
+        for (head, dir) in spawns {
+            if !taken.insert(head.move_in_dir(*dir)) {
+                return false;
+            }
+        }
+        taken.iter().all(|coord| matches!(self.board.get(coord), Some(tile) if !tile.is_hole()))
+    }
+
+    // the distance from the centre to the farthest tile
+    pub fn radius(&self) -> i32 {
+        self.board.keys().map(|coord| coord.distance(&HexCoordinates::origin())).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let level = Level::parse("
+name: Pond
+direction: East
+
+map:
+ . . . . .
+. . S . ~ @
+ . . . . .
+").unwrap();
+        assert_eq!(level.name, "Pond");
+        assert_eq!(level.direction, Direction::East);
+        assert_eq!(level.start, HexCoordinates::new(-1, 1, 0));
+        assert_eq!(level.apples, vec![HexCoordinates::new(2, -2, 0)]);
+        assert!(level.board.get(&HexCoordinates::new(1, -1, 0)).unwrap().is_hole());
+        assert!(!level.board.get(&HexCoordinates::new(1, -2, 1)).unwrap().is_hole());
+        assert_eq!(level.board.values().filter(|tile| !tile.is_hole()).count(), 15);
+        // all land is surrounded by holes
+        for (coord, tile) in &level.board {
+            if !tile.is_hole() {
+                assert!(coord.neighbors().iter().all(|neighbour| level.board.contains_key(neighbour)));
+            }
+        }
+        assert_eq!(level.radius(), 4);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Level::parse("name: Nothing\n").is_err());
+        assert!(Level::parse("colour: red\nmap:\n. S . .\n").is_err());
+        assert!(Level::parse("map:\n. . . .\n").is_err());
+        assert!(Level::parse("map:\n. . S . . S\n").is_err());
+        assert!(Level::parse("map:\n. . S . . x\n").is_err());
+        assert!(Level::parse("map:\n. . S . .\n  .\n").is_err());
+        assert!(Level::parse("map:\n. . S ~ .\n").is_err());
+        assert!(Level::parse("map:\n~ S . . .\n").is_err());
+        assert!(Level::parse("direction: East\nmap:\n. . S . .\n").is_ok());
+        assert!(Level::parse("direction: North\nmap:\n. . S . .\n").is_err());
+    }
+
+    #[test]
+    fn test_spawns() {
+        let level = Level::hexagon(9);
+        assert_eq!(level.spawns(1).unwrap(), vec![(HexCoordinates::origin(), Direction::West)]);
+        let spawns = level.spawns(2).unwrap();
+        assert_eq!(spawns, vec![(HexCoordinates::new(4, -4, 0), Direction::West), (HexCoordinates::new(-4, 4, 0), Direction::East)]);
+        for players in 2..5 {
+            let spawns = level.spawns(players).unwrap();
+            assert_eq!(spawns.len(), players);
+            assert!(spawns.iter().all(|(head, _)| head.distance(&level.start) == 4));
+        }
+        assert!(Level::hexagon(3).spawns(2).is_err());
+        assert!(Level::parse("map:\n. . S . .\n").unwrap().spawns(2).is_err());
+    }
+
+    #[test]
+    fn test_levels_dir() {
+        for entry in fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/levels")).unwrap() {
+            let path = entry.unwrap().path();
+            assert!(Level::load(&path).is_ok(), "{:?}", Level::load(&path).err());
+        }
+    }
+}
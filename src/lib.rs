@@ -0,0 +1,21 @@
+pub mod ai;
+pub mod board;
+pub mod broadcast;
+pub mod client;
+pub mod config;
+pub mod direction;
+pub mod env;
+pub mod game;
+pub mod hexagon;
+pub mod highscore;
+pub mod input;
+pub mod island;
+pub mod level;
+pub mod lockstep;
+pub mod protocol;
+pub mod replay;
+pub mod save;
+pub mod server;
+pub mod sim;
+pub mod snake;
+pub mod tile;
@@ -0,0 +1,480 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use crate::config::GameConfig;
+use crate::direction::Turn;
+use crate::game::GameState;
+use crate::hexagon::HexCoordinates;
+use crate::level::Level;
+use crate::protocol::{self, Connection, Event, PROTOCOL_VERSION};
+
+// how many ticks after it's made a turn is played, time enough for it to get to the other peers
+pub const DEFAULT_INPUT_DELAY: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeerMessage {
+    Hello { version: u32, name: String },
+    // the host starts the game once everyone is in, like the server's welcome
+    Start { version: u32, player: usize, names: Vec<String>, seed: u64, config: GameConfig, level: Option<String>, delay: u32 },
+    // the turns the player makes on the tick
+    Input { player: usize, tick: u32, turns: Vec<Turn> },
+    Hash { player: usize, hash: StateHash },
+    Error { message: String },
+}
+
+// What the game looks like after a tick, small enough to send every tick. The board and every snake get a hash
+// of their own so a desync can say which of them went wrong.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateHash {
+    pub tick: u32,
+    pub board: u64,
+    pub apple: HexCoordinates,
+    pub snakes: Vec<u64>,
+    pub scores: Vec<i32>,
+}
+
+impl StateHash {
+    pub fn of(game: &GameState) -> StateHash {
+        let mut board = Fnv::new();
+        for (coord, tile) in game.get_board() {
+            board.coordinates(coord);
+            board.write(&[tile.is_hole() as u8]);
+        }
+        let snakes = game.get_snakes().enumerate()
+            .map(|(player, snake)| {
+                let mut hash = Fnv::new();
+                for part in snake.body() {
+                    hash.coordinates(&part.coordinates);
+                    hash.write(&[part.dir_from.value() as u8, part.dir_to.value() as u8]);
+                }
+                hash.write(&[snake.falling as u8, game.is_alive(player) as u8]);
+                hash.finish()
+            })
+            .collect();
+        StateHash { tick: game.get_tick(), board: board.finish(), apple: game.get_apple().clone(), snakes, scores: game.get_scores() }
+    }
+
+    // what's not the same in the other hash of the same tick, one line each
+    pub fn differences(&self, other: &StateHash) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.board != other.board {
+            differences.push("the holes are not the same".to_string());
+        }
+        if self.apple != other.apple {
+            differences.push(format!("the apple is at {} here and at {} there", coordinates(&self.apple), coordinates(&other.apple)));
+        }
+        for (snake, (here, there)) in self.snakes.iter().zip(&other.snakes).enumerate() {
+            if here != there {
+                differences.push(format!("snake {} is not where it is there", snake + 1));
+            }
+        }
+        if self.snakes.len() != other.snakes.len() {
+            differences.push(format!("there are {} snakes here and {} there", self.snakes.len(), other.snakes.len()));
+        }
+        if self.scores != other.scores {
+            differences.push(format!("the scores are {:?} here and {:?} there", self.scores, other.scores));
+        }
+        differences
+    }
+}
+
+fn coordinates(coord: &HexCoordinates) -> String {
+    format!("({}, {}, {})", coord.get_r(), coord.get_g(), coord.get_b())
+}
+
+// FNV-1a over little-endian bytes, so every peer gets the same hash whatever it runs on
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn coordinates(&mut self, coord: &HexCoordinates) {
+        for value in [coord.get_r(), coord.get_g(), coord.get_b()] {
+            self.write(&value.to_le_bytes());
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// the first tick where a peer's game wasn't the same as ours
+#[derive(Debug, Clone, PartialEq)]
+pub struct Desync {
+    pub player: usize,
+    pub local: Box<StateHash>,
+    pub remote: Box<StateHash>,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "out of sync with player {} at tick {}", self.player + 1, self.local.tick)?;
+        for difference in self.local.differences(&self.remote) {
+            write!(f, "\n  {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+// Every peer runs the game itself, and a tick is only played once the turns of every player for it are in.
+// The turns made on a tick are played delay ticks later, so the peers don't wait on each other as long as
+// the messages take less than that to arrive. After every tick the peers swap hashes of their games to check
+// they're still the same.
+pub struct Lockstep {
+    player: usize,
+    players: usize,
+    delay: u32,
+    // the turns of every player for the ticks to come, None for the ones not in yet
+    inputs: BTreeMap<u32, Vec<Option<Vec<Turn>>>>,
+    // ours and how many of the others have been checked against it
+    hashes: BTreeMap<u32, (StateHash, usize)>,
+    // the others' that came before ours
+    early_hashes: Vec<(usize, StateHash)>,
+}
+
+impl Lockstep {
+    pub fn new(player: usize, players: usize, delay: u32) -> Lockstep {
+        // nobody could have turned before the game started
+        let inputs = (0..delay).map(|tick| (tick, vec![Some(Vec::new()); players])).collect();
+        Lockstep { player, players, delay, inputs, hashes: BTreeMap::new(), early_hashes: Vec::new() }
+    }
+
+    pub fn get_player(&self) -> usize {
+        self.player
+    }
+
+    pub fn get_delay(&self) -> u32 {
+        self.delay
+    }
+
+    // whether our turns from the given tick still have to be sent
+    pub fn needs_input(&self, tick: u32) -> bool {
+        self.inputs.get(&(tick + self.delay)).is_none_or(|turns| turns[self.player].is_none())
+    }
+
+    // our turns made on the given tick, they get played after the delay; the message is for the other peers
+    pub fn local_turns(&mut self, tick: u32, turns: Vec<Turn>) -> PeerMessage {
+        let tick = tick + self.delay;
+        self.set_turns(self.player, tick, turns.clone());
+        PeerMessage::Input { player: self.player, tick, turns }
+    }
+
+    fn set_turns(&mut self, player: usize, tick: u32, turns: Vec<Turn>) {
+        self.inputs.entry(tick).or_insert_with(|| vec![None; self.players])[player] = Some(turns);
+    }
+
+    // takes in the other peers' turns and hashes
+    pub fn receive(&mut self, message: &PeerMessage) -> Result<(), Desync> {
+        match message {
+            PeerMessage::Input { player, tick, turns } if *player < self.players && *player != self.player => {
+                self.set_turns(*player, *tick, turns.clone());
+            }
+            PeerMessage::Hash { player, hash } if *player < self.players && *player != self.player => {
+                self.early_hashes.push((*player, hash.clone()));
+                self.check_hashes()?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // every player's turns for the tick, once they are all in
+    pub fn take_turns(&mut self, tick: u32) -> Option<Vec<Vec<Turn>>> {
+        if self.inputs.get(&tick)?.iter().any(|turns| turns.is_none()) {
+            return None;
+        }
+        self.inputs.remove(&tick).map(|turns| turns.into_iter().flatten().collect())
+    }
+
+    // to be called after every tick, the message is the hash for the other peers to check
+    pub fn stepped(&mut self, game: &GameState) -> Result<PeerMessage, Desync> {
+        let hash = StateHash::of(game);
+        if self.players > 1 {
+            self.hashes.insert(hash.tick, (hash.clone(), 0));
+        }
+        self.check_hashes()?;
+        Ok(PeerMessage::Hash { player: self.player, hash })
+    }
+
+    fn check_hashes(&mut self) -> Result<(), Desync> {
+        let mut index = 0;
+        while index < self.early_hashes.len() {
+            let (player, remote) = &self.early_hashes[index];
+            let Some((local, checked)) = self.hashes.get_mut(&remote.tick) else {
+                index += 1;
+                continue;
+            };
+            if local != remote {
+                return Err(Desync { player: *player, local: Box::new(local.clone()), remote: Box::new(remote.clone()) });
+            }
+            *checked += 1;
+            if *checked == self.players - 1 {
+                self.hashes.remove(&remote.tick);
+            }
+            self.early_hashes.swap_remove(index);
+        }
+        Ok(())
+    }
+}
+
+// A peer's end of a game played in lockstep. The host waits for the others to join and starts the game;
+// after that it only passes every peer's messages on to the others, its game is no more the real one than theirs.
+pub struct Session {
+    pub lockstep: Lockstep,
+    pub names: Vec<String>,
+    // the level file's text if the game is on one
+    pub level_text: Option<String>,
+    host: bool,
+    // the host has one for every other peer in player order, the others only the one to the host
+    connections: Vec<Connection>,
+    messages: Receiver<(usize, Option<PeerMessage>)>,
+}
+
+impl Session {
+    // waits until the config's number of players are in, the host being the first; who comes is sent to the events
+    pub fn host(addr: impl ToSocketAddrs, config: &GameConfig, name: &str, seed: u64, delay: u32, events: &Sender<Event>)
+                -> io::Result<(Session, Level, GameState)> {
+        let level = config.load_level(seed)?;
+        let level_text = protocol::level_text(config)?;
+        let listener = TcpListener::bind(addr)?;
+        let _ = events.send(Event::Waiting { addr: listener.local_addr()?, players: config.players - 1 });
+        let mut connections = Vec::new();
+        let mut names = vec![name.to_string()];
+        while names.len() < config.players {
+            let (stream, addr) = listener.accept()?;
+            let (mut connection, greeting) = protocol::greeting::<PeerMessage>(stream)?;
+            let error = match greeting {
+                Ok(PeerMessage::Hello { version, name }) if version == PROTOCOL_VERSION => {
+                    let _ = events.send(Event::WantsToPlay { name: name.clone(), addr });
+                    let _ = events.send(Event::Joined { name: name.clone(), player: names.len() });
+                    connections.push(connection);
+                    names.push(name);
+                    continue;
+                }
+                Ok(PeerMessage::Hello { version, .. }) => {
+                    format!("the host speaks version {} of the protocol, not {}", PROTOCOL_VERSION, version)
+                }
+                Ok(_) => "expected a hello message".to_string(),
+                Err(err) => err.to_string()
+            };
+            let _ = events.send(Event::TurnedAway { addr, reason: error.clone() });
+            let _ = connection.send(&PeerMessage::Error { message: error });
+        }
+        let shared_config = GameConfig { level: None, ..config.clone() };
+        for (index, connection) in connections.iter_mut().enumerate() {
+            connection.send(&PeerMessage::Start {
+                version: PROTOCOL_VERSION,
+                player: index + 1,
+                names: names.clone(),
+                seed,
+                config: shared_config.clone(),
+                level: level_text.clone(),
+                delay,
+            })?;
+        }
+        let game = GameState::from_level(config, &level, seed);
+        let session = Session::start(Lockstep::new(0, config.players, delay), names, level_text, true, connections)?;
+        Ok((session, level, game))
+    }
+
+    // waits for the host to start the game
+    pub fn join(addr: impl ToSocketAddrs, name: &str) -> io::Result<(Session, Level, GameState)> {
+        let mut connection = Connection::new(TcpStream::connect(addr)?)?;
+        connection.send(&PeerMessage::Hello { version: PROTOCOL_VERSION, name: name.to_string() })?;
+        match connection.receive()? {
+            Some(PeerMessage::Start { version, player, names, seed, config, level: level_text, delay }) => {
+                if version != PROTOCOL_VERSION {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("the host speaks version {} of the protocol", version)));
+                }
+                let (level, game) = protocol::start_game(&config, seed, level_text.as_deref())?;
+                // the host is player 0
+                if player == 0 || player >= config.players || names.len() != config.players {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "the host's start doesn't add up"));
+                }
+                let session = Session::start(Lockstep::new(player, config.players, delay), names, level_text, false, vec![connection])?;
+                Ok((session, level, game))
+            }
+            Some(PeerMessage::Error { message }) => Err(io::Error::other(message)),
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "the host didn't start the game")),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the host closed the connection"))
+        }
+    }
+
+    fn start(lockstep: Lockstep, names: Vec<String>, level_text: Option<String>, host: bool, connections: Vec<Connection>)
+             -> io::Result<Session> {
+        let messages = protocol::read_messages(connections.iter())?;
+        Ok(Session { lockstep, names, level_text, host, connections, messages })
+    }
+
+    // to every other peer, through the host for the ones that aren't it
+    pub fn send(&mut self, message: &PeerMessage) -> Result<(), String> {
+        self.send_except(message, None)
+    }
+
+    fn send_except(&mut self, message: &PeerMessage, except: Option<usize>) -> Result<(), String> {
+        for (index, connection) in self.connections.iter_mut().enumerate().filter(|(index, _)| Some(*index) != except) {
+            connection.send(message).map_err(|err| format!("could not send to {}: {}", self.names[if self.host { index + 1 } else { 0 }], err))?;
+        }
+        Ok(())
+    }
+
+    // takes in whatever came since the last time; the game can't go on after an error, be it a desync or a peer leaving
+    pub fn receive(&mut self) -> Result<(), String> {
+        let messages: Vec<_> = self.messages.try_iter().collect();
+        for (index, message) in messages {
+            let from = if self.host { index + 1 } else { 0 };
+            let message = message.ok_or_else(|| format!("{} left the game", self.names[from]))?;
+            if self.host {
+                self.send_except(&message, Some(index))?;
+            }
+            match message {
+                PeerMessage::Error { message } => return Err(message),
+                message => self.lockstep.receive(&message).map_err(|desync| desync.to_string())?
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use crate::direction::Direction;
+
+    // two peers passing their messages straight to each other
+    fn play(peers: &mut [(Lockstep, GameState); 2], ticks: u32, turns: impl Fn(usize, u32) -> Vec<Turn>) -> Result<(), Desync> {
+        for _ in 0..ticks {
+            for player in 0..2 {
+                let tick = peers[player].1.get_tick();
+                let message = peers[player].0.local_turns(tick, turns(player, tick));
+                peers[1 - player].0.receive(&message)?;
+            }
+            for player in 0..2 {
+                let (lockstep, game) = &mut peers[player];
+                let tick = game.get_tick();
+                let all_turns = lockstep.take_turns(tick).expect("the turns are in");
+                game.step_players(&all_turns);
+                let message = lockstep.stepped(game)?;
+                peers[1 - player].0.receive(&message)?;
+            }
+        }
+        Ok(())
+    }
+
+    // far enough apart on the island that they can head for each other for a few ticks
+    fn peers(config: &GameConfig) -> [(Lockstep, GameState); 2] {
+        [0, 1].map(|player| (Lockstep::new(player, 2, DEFAULT_INPUT_DELAY), GameState::new(config, 8)))
+    }
+
+    #[test]
+    fn test_input_delay() {
+        let config = GameConfig { radius: 15, players: 2, ..GameConfig::default() };
+        let mut peers = peers(&config);
+        let start = peers[0].1.get_player_snake(0).get_dir();
+        play(&mut peers, 1, |player, tick| if player == 0 && tick == 0 { vec![Turn::Left] } else { Vec::new() }).unwrap();
+        assert!(peers[0].0.needs_input(1));
+        assert!(!peers[0].0.needs_input(0));
+        // the turn is only played on the tick after the delay
+        play(&mut peers, DEFAULT_INPUT_DELAY - 1, |_, _| Vec::new()).unwrap();
+        assert_eq!(peers[1].1.get_player_snake(0).get_dir(), start);
+        play(&mut peers, 1, |_, _| Vec::new()).unwrap();
+        assert_eq!(peers[1].1.get_player_snake(0).get_dir(), Direction::change_dir(start, Turn::Left));
+        assert_eq!(StateHash::of(&peers[0].1), StateHash::of(&peers[1].1));
+        assert!(peers[0].0.take_turns(peers[0].1.get_tick() + DEFAULT_INPUT_DELAY).is_none());
+        assert!(peers[0].0.hashes.is_empty() && peers[1].0.early_hashes.is_empty());
+    }
+
+    #[test]
+    fn test_desync() {
+        let config = GameConfig { radius: 15, players: 2, ..GameConfig::default() };
+        let mut peers = peers(&config);
+        play(&mut peers, 5, |player, _| if player == 1 { vec![Turn::Right] } else { Vec::new() }).unwrap();
+        // one of them steps without the other, as if it had been told a different turn
+        peers[0].1.step_players(&[vec![Turn::Left], Vec::new()]);
+        let desync = peers[0].0.stepped(&peers[0].1).unwrap();
+        peers[1].1.step_players(&[Vec::new(), Vec::new()]);
+        let PeerMessage::Hash { hash, .. } = peers[1].0.stepped(&peers[1].1).unwrap() else { panic!("expected a hash") };
+        let report = peers[0].0.receive(&PeerMessage::Hash { player: 1, hash }).unwrap_err();
+        assert_eq!(report.player, 1);
+        assert_eq!(report.to_string(), "out of sync with player 2 at tick 6\n  snake 1 is not where it is there");
+        assert!(peers[1].0.receive(&desync).is_err());
+    }
+
+    #[test]
+    fn test_bad_start() {
+        // a host that starts the game with whatever it's given
+        let start = |player: usize, names: usize, config: GameConfig| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                let mut connection = Connection::new(listener.accept().unwrap().0).unwrap();
+                connection.receive::<PeerMessage>().unwrap();
+                let names = vec!["edi".to_string(); names];
+                let _ = connection.send(&PeerMessage::Start { version: PROTOCOL_VERSION, player, names, seed: 1, config, level: None, delay: 2 });
+            });
+            Session::join(addr, "guest").map(|_| ())
+        };
+        let two = GameConfig { players: 2, ..GameConfig::default() };
+        assert!(start(1, 2, two.clone()).is_ok());
+        assert!(start(0, 2, two.clone()).is_err());
+        assert!(start(2, 2, two.clone()).is_err());
+        assert!(start(1, 3, two.clone()).is_err());
+        assert_eq!(start(1, 2, GameConfig { holes_interval: 0, ..two }).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_session() {
+        let config = GameConfig { radius: 6, players: 2, rivals: 1, ..GameConfig::default() };
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let host = thread::spawn(move || Session::host(("127.0.0.1", port), &config, "host", 3, 2, &mpsc::channel().0).unwrap());
+        let guest = loop {
+            match Session::join(("127.0.0.1", port), "guest") {
+                Ok(guest) => break guest,
+                Err(_) => thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        };
+        let host = host.join().unwrap();
+        let run = |(mut session, _, mut game): (Session, Level, GameState)| thread::spawn(move || {
+            let mut rival = crate::ai::Bot::Rival(game.get_config().rival_difficulty).controller();
+            while !game.is_over() && game.get_tick() < 60 {
+                session.receive().unwrap();
+                let tick = game.get_tick();
+                if session.lockstep.needs_input(tick) {
+                    let turns = if tick as usize % 4 == session.lockstep.get_player() { vec![Turn::Left] } else { Vec::new() };
+                    let message = session.lockstep.local_turns(tick, turns);
+                    session.send(&message).unwrap();
+                }
+                match session.lockstep.take_turns(tick) {
+                    Some(mut turns) => {
+                        turns.push(rival.next_turns(&game, 2));
+                        game.step_players(&turns);
+                        let message = session.lockstep.stepped(&game).unwrap();
+                        session.send(&message).unwrap();
+                    }
+                    None => thread::sleep(std::time::Duration::from_millis(1)),
+                }
+            }
+            // the session is kept until both are done, the other one would take it closing for leaving the game
+            (session, StateHash::of(&game))
+        });
+        let (host, guest) = (run(host), run(guest));
+        let (session, host_hash) = host.join().unwrap();
+        assert_eq!(session.names, vec!["host".to_string(), "guest".to_string()]);
+        assert_eq!(guest.join().unwrap().1, host_hash);
+    }
+}
@@ -1,142 +1,800 @@
-use ggez::event::{EventHandler, KeyCode, KeyMods};
+use ggez::event::{Axis, Button, EventHandler, GamepadId, KeyCode, KeyMods, MouseButton};
 use ggez::{Context, ContextBuilder, event, GameError, GameResult, graphics, timer};
 use ggez::conf::{Conf, FullscreenType, WindowMode};
-use ggez::graphics::{Color, Drawable, DrawMode, Mesh, Rect, Text};
-use indexmap::map::IndexMap;
-use crate::tile::Tile;
-use rand::Rng;
-use crate::hexagon::HexCoordinates;
-use crate::snake::Snake;
-use ggez::graphics::mint::Point2;
+use ggez::graphics::{Color, Drawable, Rect, Text};
 use ggez::graphics::DrawParam;
-use crate::direction::Direction;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use snek::ai::{self, Bot, Controller};
+use snek::broadcast::Broadcast;
+use snek::client::{self, NetClient};
+use snek::config::{Difficulty, GameConfig};
+use snek::direction::{Direction, Turn};
+use snek::game::GameState;
+use snek::hexagon::HexCoordinates;
+use snek::highscore::{self, HighScores};
+use snek::input::InputQueue;
+use snek::level::Level;
+use snek::lockstep::{self, Session};
+use snek::protocol::{self, DEFAULT_PORT, ServerMessage, Snapshot};
+use snek::replay::Replay;
+use snek::save::SavedGame;
+use snek::server::SNAPSHOT_INTERVAL;
+use snek::sim::{self, Summary};
+use snek::snake;
+use crate::bindings::{Action, Bindings};
+use crate::screen::{Screen, ScreenChange};
 
-mod hexagon;
-mod tile;
-mod snake;
-mod direction;
+mod bindings;
+mod render;
+mod screen;
 
-const HEX_SIDE: f32 = 30.0;
-const GRID_RADIUS: i32 = 9;
-const ADD_WALLS_NUM: i32 = 3;
-const ADD_WALLS_INTERVAL: i32 = 3;
-const UPDATE_SPEED : u32 = 2;
+const FAST_FORWARD: u32 = 4;
+// how much closer the view is when following a snake
+const FOLLOW_ZOOM: f32 = 2.0;
+// how long a note like "SAVED" stays on the screen
+const NOTICE_TIME: Duration = Duration::from_secs(2);
+const REPLAY_DIR: &str = "replays";
+// a headless game stops here even if the snake is still going, it's usually circling an apple it can't get to
+const HEADLESS_MAX_TICKS: u32 = 10_000;
+
+pub struct Playback {
+    paused: bool,
+    step: bool,
+    fast_forward: bool,
+}
 
 pub struct MainState {
-    board: IndexMap<HexCoordinates, Tile>,
-    apple: HexCoordinates,
-    prev_apple: Option<HexCoordinates>,
-    snake: Snake,
-    score: i32,
-    end_game: bool,
+    base_config: GameConfig,
+    difficulty: Option<Difficulty>,
+    config: GameConfig,
+    level: Level,
+    screen: Screen,
+    fixed_seed: Option<u64>,
+    game: GameState,
+    // one queue per player
+    inputs: Vec<InputQueue>,
+    // one per snake, None for the ones the players steer themselves
+    controllers: Vec<Option<Box<dyn Controller>>>,
+    // the first player's snake steers itself
+    autopilot: bool,
+    bindings: Bindings,
+    // gamepads in the order they were first used, the n-th one plays for player n; with its stick and where it pointed last
+    gamepads: Vec<(GamepadId, (f32, f32), Option<Turn>)>,
+    replay: Replay,
+    replay_saved: bool,
+    playback: Option<Playback>,
+    player_name: String,
+    highscores: HighScores,
+    highscore_place: Option<usize>,
+    play_time: Duration,
+    // set when playing on a server, the game then only moves on the ticks it sends
+    net: Option<NetClient>,
+    // set when playing in lockstep with other peers, a tick then waits for everyone's turns
+    session: Option<Session>,
+    // why an online game had to stop
+    net_error: Option<String>,
+    // sends the game to the spectators as it's played
+    broadcast: Option<Broadcast>,
+    // the snake a spectator's view stays on
+    follow: Option<usize>,
+    // the game left open last time, offered on the title screen
+    resume: Option<SavedGame>,
+    notice: Option<(String, Instant)>,
 }
 
 impl MainState {
-    fn new() -> GameResult<MainState> {
-        let mut map = IndexMap::new();
-        for radius in 0_i32..GRID_RADIUS + 1 {
-            for r in -radius..radius + 1 {
-                for b in -radius..radius + 1 {
-                    for g in -radius..radius + 1 {
-                        if (r.abs() + b.abs() + g.abs() == 2 * radius) && (r + b + g == 0) {
-                            if r.abs() == GRID_RADIUS || b.abs() == GRID_RADIUS || g.abs() == GRID_RADIUS {
-                                map.insert(HexCoordinates::new(r, b, g), Tile::new(r, g, b, true));
-                            } else {
-                                map.insert(HexCoordinates::new(r, b, g), Tile::new(r, g, b, false));
-                            }
+    fn new(base_config: GameConfig, difficulty: Option<Difficulty>, fixed_seed: Option<u64>, player_name: String) -> GameResult<MainState> {
+        let mut config = base_config.clone();
+        if let Some(difficulty) = difficulty {
+            difficulty.apply(&mut config);
+        }
+        let seed = fixed_seed.unwrap_or_else(rand::random);
+        let level = config.load_level(seed).map_err(|err| GameError::ResourceLoadError(err.to_string()))?;
+        let highscores = HighScores::load_default().unwrap_or_else(|err| {
+            eprintln!("could not load high scores: {}", err);
+            HighScores::default()
+        });
+        Ok(MainState {
+            screen: Screen::Title,
+            fixed_seed,
+            game: GameState::from_level(&config, &level, seed),
+            inputs: Vec::new(),
+            controllers: Vec::new(),
+            autopilot: false,
+            bindings: Bindings::default(),
+            gamepads: Vec::new(),
+            replay: Replay::new(&config, seed),
+            level,
+            base_config,
+            difficulty,
+            config,
+            replay_saved: false,
+            playback: None,
+            player_name,
+            highscores,
+            highscore_place: None,
+            play_time: Duration::ZERO,
+            net: None,
+            session: None,
+            net_error: None,
+            broadcast: None,
+            follow: None,
+            resume: None,
+            notice: None,
+        })
+    }
+
+    fn from_replay(replay: Replay, hex_side: f32) -> GameResult<MainState> {
+        let level = replay.config.load_level(replay.seed).map_err(|err| GameError::ResourceLoadError(err.to_string()))?;
+        Ok(MainState {
+            base_config: GameConfig { hex_side, ..replay.config.clone() },
+            difficulty: None,
+            config: GameConfig { hex_side, ..replay.config.clone() },
+            screen: Screen::Playing,
+            fixed_seed: Some(replay.seed),
+            game: GameState::from_level(&replay.config, &level, replay.seed),
+            level,
+            inputs: Vec::new(),
+            controllers: Vec::new(),
+            autopilot: false,
+            bindings: Bindings::default(),
+            gamepads: Vec::new(),
+            replay,
+            replay_saved: true,
+            playback: Some(Playback { paused: false, step: false, fast_forward: false }),
+            player_name: String::new(),
+            highscores: HighScores::default(),
+            highscore_place: None,
+            play_time: Duration::ZERO,
+            net: None,
+            session: None,
+            net_error: None,
+            broadcast: None,
+            follow: None,
+            resume: None,
+            notice: None,
+        })
+    }
+
+    // a game started elsewhere, by a server or the host; its config but for how the game looks and is steered here
+    fn online(level: Level, game: GameState, local_config: &GameConfig, player_name: String) -> MainState {
+        let config = GameConfig { hex_side: local_config.hex_side, controls: local_config.controls, ..game.get_config().clone() };
+        MainState {
+            base_config: config.clone(),
+            difficulty: None,
+            screen: Screen::Playing,
+            fixed_seed: Some(game.get_seed()),
+            inputs: vec![InputQueue::new(); config.players],
+            controllers: ai::controllers(&config, 0),
+            autopilot: false,
+            bindings: Bindings::default(),
+            gamepads: Vec::new(),
+            replay: Replay::new(&config, game.get_seed()),
+            replay_saved: true,
+            playback: None,
+            player_name,
+            highscores: HighScores::default(),
+            highscore_place: None,
+            play_time: Duration::ZERO,
+            net: None,
+            session: None,
+            net_error: None,
+            broadcast: None,
+            follow: None,
+            resume: None,
+            notice: None,
+            game,
+            level,
+            config,
+        }
+    }
+
+    fn submit_score(&mut self) {
+        self.highscore_place = self.highscores.submit_game(&self.game, &self.player_name, self.play_time.as_secs());
+        if self.highscore_place.is_some() {
+            if let Err(err) = self.highscores.save_default() {
+                eprintln!("could not save high scores: {}", err);
+            }
+        }
+    }
+
+    // cycles between the presets and the configured speed
+    fn select_difficulty(&mut self, forward: bool) {
+        let options: Vec<Option<Difficulty>> = std::iter::once(None).chain(Difficulty::ALL.iter().copied().map(Some)).collect();
+        let index = options.iter().position(|option| option == &self.difficulty).unwrap_or(0);
+        let next = if forward { index + 1 } else { index + options.len() - 1 };
+        self.difficulty = options[next % options.len()];
+        self.config = self.base_config.clone();
+        if let Some(difficulty) = self.difficulty {
+            difficulty.apply(&mut self.config);
+        }
+    }
+
+    fn start_game(&mut self) {
+        self.discard_resume();
+        if let Some(playback) = &mut self.playback {
+            playback.paused = false;
+            self.game = GameState::from_level(&self.replay.config, &self.level, self.replay.seed);
+        } else {
+            let seed = self.fixed_seed.unwrap_or_else(rand::random);
+            println!("seed: {}", seed);
+            if self.config.level.is_none() {
+                self.level = self.config.island_level(seed);
+            }
+            self.game = GameState::from_level(&self.config, &self.level, seed);
+            self.replay = Replay::new(&self.config, seed);
+            self.replay_saved = false;
+        }
+        self.inputs = vec![InputQueue::new(); self.game.get_config().players];
+        self.controllers = ai::controllers(self.game.get_config(), if self.autopilot { 1 } else { 0 });
+        self.highscore_place = None;
+        self.play_time = Duration::ZERO;
+        self.screen = Screen::Playing;
+        self.start_broadcast();
+    }
+
+    // the spectators get the game from its start, with the snakes named as they are here
+    fn start_broadcast(&self) {
+        let Some(broadcast) = &self.broadcast else {
+            return;
+        };
+        let config = self.game.get_config();
+        let level = match &self.session {
+            Some(session) => session.level_text.clone(),
+            None => protocol::level_text(config).unwrap_or_else(|err| {
+                eprintln!("could not read the level for the spectators: {}", err);
+                None
+            })
+        };
+        let names = (0..config.players).map(|player| self.snake_name(player)).collect();
+        broadcast.start(self.game.get_seed(), config, level, names);
+        // a loaded game is caught up with from its replay
+        for tick in 0..self.game.get_tick() {
+            broadcast.send(&ServerMessage::Tick { tick, turns: self.replay.all_turns_at(tick) });
+        }
+    }
+
+    // a game played here and still going, online games are the server's or everyone's
+    fn can_save(&self) -> bool {
+        !self.is_online() && self.playback.is_none() && !self.game.is_over()
+            && matches!(self.screen, Screen::Playing | Screen::Paused)
+    }
+
+    fn save(&mut self, path: Option<PathBuf>) -> bool {
+        let saved = SavedGame::new(self.game.clone(), self.replay.clone(), self.difficulty, self.play_time.as_secs());
+        let result = match &path {
+            Some(path) => saved.save(path).map_err(|err| format!("could not save the game to {}: {}", path.display(), err)),
+            None => Err("there's no data directory to save the game in".to_string())
+        };
+        if let Err(err) = &result {
+            eprintln!("{}", err);
+        }
+        result.is_ok()
+    }
+
+    fn quick_save(&mut self) {
+        if self.can_save() && self.save(SavedGame::quick_path()) {
+            self.notice = Some(("SAVED".to_string(), Instant::now()));
+        }
+    }
+
+    fn quick_load(&mut self) {
+        if self.is_online() || self.playback.is_some() {
+            return;
+        }
+        let Some(path) = SavedGame::quick_path() else {
+            return;
+        };
+        match SavedGame::load(&path) {
+            Ok(saved) => self.load(saved),
+            Err(err) => {
+                eprintln!("could not load the game from {}: {}", path.display(), err);
+                self.notice = Some(("NOTHING TO LOAD".to_string(), Instant::now()));
+            }
+        }
+    }
+
+    // the saved game's rules are the ones played by from now on, it waits paused until the player is ready
+    fn load(&mut self, saved: SavedGame) {
+        let config = saved.game.get_config();
+        let level = match config.load_level(saved.game.get_seed()) {
+            Ok(level) => level,
+            Err(err) => {
+                eprintln!("could not load the saved game's level: {}", err);
+                return;
+            }
+        };
+        self.discard_resume();
+        self.base_config = GameConfig { hex_side: self.config.hex_side, controls: self.config.controls, ..config.clone() };
+        self.config = self.base_config.clone();
+        self.difficulty = saved.difficulty;
+        self.level = level;
+        self.game = saved.game;
+        self.replay = saved.replay;
+        self.replay_saved = false;
+        self.inputs = vec![InputQueue::new(); self.config.players];
+        self.controllers = ai::controllers(&self.config, if self.autopilot { 1 } else { 0 });
+        self.highscore_place = None;
+        self.play_time = Duration::from_secs(saved.play_time_secs);
+        self.screen = Screen::Paused;
+        self.notice = Some(("LOADED".to_string(), Instant::now()));
+        self.start_broadcast();
+    }
+
+    // once another game is on, the one from last time is gone
+    fn discard_resume(&mut self) {
+        if self.resume.take().is_some() {
+            remove_autosave();
+        }
+    }
+
+    // anyone can pause or go through the menus, turns only turn the given player's snake
+    fn on_actions(&mut self, ctx: &mut Context, player: usize, actions: &[Action]) {
+        for action in actions {
+            if let Some(change) = self.screen.on_action(*action) {
+                // an online game goes on without pausing or restarting, leaving the game over screen quits
+                if self.is_online() {
+                    if self.screen == Screen::GameOver {
+                        event::quit(ctx);
+                    }
+                    return;
+                }
+                match change {
+                    ScreenChange::To(screen) => self.screen = screen,
+                    ScreenChange::NewGame => self.start_game(),
+                    ScreenChange::Quit => event::quit(ctx),
+                }
+                return;
+            }
+        }
+        for action in actions {
+            if self.screen == Screen::Title && self.playback.is_none() {
+                match action {
+                    Action::TurnLeft => self.select_difficulty(false),
+                    Action::TurnRight => self.select_difficulty(true),
+                    Action::Controls => {
+                        self.base_config.controls = self.base_config.controls.toggled();
+                        self.config.controls = self.base_config.controls;
+                    }
+                    Action::Restart => {
+                        if let Some(saved) = self.resume.take() {
+                            self.load(saved);
+                            remove_autosave();
                         }
                     }
+                    _ => {}
                 }
             }
+            match action {
+                Action::QuickSave => self.quick_save(),
+                Action::QuickLoad => self.quick_load(),
+                _ => {}
+            }
+            if self.screen != Screen::Playing {
+                continue;
+            }
+            if self.is_watching() {
+                match action {
+                    Action::TurnLeft => self.follow_next(false),
+                    Action::TurnRight => self.follow_next(true),
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(playback) = &mut self.playback {
+                match action {
+                    Action::PlaybackPause => playback.paused = !playback.paused,
+                    Action::Step => playback.step = playback.paused,
+                    Action::FastForward => playback.fast_forward = !playback.fast_forward,
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(turn) = bindings::turn_for_action(*action, self.config.controls) {
+                self.push_turn(player, turn);
+            }
+        }
+    }
+
+    fn snake_name(&self, snake: usize) -> String {
+        let names = self.net.as_ref().map(|net| &net.names).or(self.session.as_ref().map(|session| &session.names));
+        self.game.snake_name(names.map_or(&[], |names| &names[..]), snake)
+    }
+
+    fn gamepad_player(&mut self, id: GamepadId) -> usize {
+        match self.gamepads.iter().position(|(other, _, _)| *other == id) {
+            Some(player) => player,
+            None => {
+                self.gamepads.push((id, (0.0, 0.0), None));
+                self.gamepads.len() - 1
+            }
         }
-        let snake = Snake::new();
-        let mut game = MainState {
-            apple: HexCoordinates::new(0, 0, 0),
-            board: map,
-            snake,
-            score: 0,
-            end_game: false,
-            prev_apple: None,
+    }
+
+    // the snake a spectator follows, if it's in this game
+    fn followed(&self) -> Option<usize> {
+        self.follow.filter(|&player| self.is_watching() && player < self.game.get_players())
+    }
+
+    // goes round the snakes and back to the whole island
+    fn follow_next(&mut self, forward: bool) {
+        let snakes = self.game.get_players();
+        self.follow = match (self.follow, forward) {
+            (None, true) => Some(0),
+            (None, false) => Some(snakes - 1),
+            (Some(snake), true) if snake + 1 < snakes => Some(snake + 1),
+            (Some(snake), false) if snake > 0 => Some(snake - 1),
+            _ => None
         };
-        game.apple = game.get_random_tile();
-        Ok(game)
     }
 
-    fn get_random_tile(&self) -> HexCoordinates {
-        let board_size = self.board.len();
-        let mut rand_index = rand::thread_rng().gen_range(0..board_size);
-        let mut pair = self.board.get_index(rand_index).unwrap();
-        while pair.1.is_hole()  {
-            rand_index = rand::thread_rng().gen_range(0..board_size);
-            pair = self.board.get_index(rand_index).unwrap();
+    fn push_turn(&mut self, player: usize, turn: Turn) {
+        // online the first player's controls steer this client's snake and the others are steered elsewhere
+        let player = match self.is_online() {
+            _ if self.is_watching() => return,
+            true if player == 0 => self.own_player(),
+            true => return,
+            false => player
+        };
+        // a pad or a key set for a snake that isn't in this game
+        if player >= self.inputs.len() {
+            return;
         }
-        return pair.0.clone();
+        if self.screen == Screen::Playing && self.playback.is_none() && !self.game.is_over() && self.game.is_alive(player)
+            && matches!(self.controllers.get(player), Some(None)) && self.inputs[player].push(turn) {
+            // the queue here follows the server's one, so it's known which way the head will be looking
+            if let Some(net) = &mut self.net {
+                if let Err(err) = net.send_turns(vec![turn]) {
+                    eprintln!("could not send the turn: {}", err);
+                }
+            }
+        }
+    }
+
+    fn is_online(&self) -> bool {
+        self.net.is_some() || self.session.is_some()
+    }
+
+    fn is_watching(&self) -> bool {
+        self.net.as_ref().is_some_and(|net| net.player.is_none())
     }
 
-    fn check_if_falls(&mut self) {
-        let head_coord = self.snake.get_head();
-        let head_tile = self.board.get(head_coord).unwrap();
-        if head_tile.is_hole() {
-            self.end_game = true;
-            self.snake.falling = true;
+    // the snake steered here, the first one unless it's an online game
+    fn own_player(&self) -> usize {
+        match (&self.net, &self.session) {
+            (Some(net), _) => net.player.unwrap_or(0),
+            (_, Some(session)) => session.lockstep.get_player(),
+            _ => 0
         }
     }
 
-    fn check_if_eaten_apple(&mut self) {
-        if self.snake.get_head() == &self.apple {
-            self.prev_apple = Some(self.apple.clone());
-            self.apple = self.get_random_tile();
-            self.score = self.score + 1;
-            if self.score % ADD_WALLS_INTERVAL == 0 {
-                self.add_holes();
+    // the whole report goes to the terminal, the window shows it too
+    fn stop_online(&mut self, err: String) {
+        eprintln!("{}", err);
+        self.net_error = Some(err);
+        self.screen = Screen::GameOver;
+    }
+
+    // steps the game as the server says, instead of on the clock
+    fn receive(&mut self) {
+        let messages = match &self.net {
+            Some(net) if self.net_error.is_none() => net.poll(),
+            _ => return
+        };
+        let player = self.own_player();
+        for message in messages {
+            if let Err(err) = client::apply(&mut self.game, &message) {
+                self.stop_online(err);
+                return;
             }
+            match message {
+                ServerMessage::Tick { .. } if !self.is_watching() => {
+                    self.inputs[player].next_turns();
+                }
+                ServerMessage::Welcome { names, seed, config, level, .. } => {
+                    // a spectator stays on for the next game
+                    match protocol::start_game(&config, seed, level.as_deref()) {
+                        Ok((level, game)) => {
+                            self.config = GameConfig { hex_side: self.config.hex_side, controls: self.config.controls, ..config };
+                            self.level = level;
+                            self.game = game;
+                            self.controllers = ai::controllers(&self.config, 0);
+                            self.inputs = vec![InputQueue::new(); self.config.players];
+                            self.screen = Screen::Playing;
+                            if let Some(net) = &mut self.net {
+                                net.names = names;
+                            }
+                        }
+                        Err(err) => {
+                            self.stop_online(err.to_string());
+                            return;
+                        }
+                    }
+                }
+                ServerMessage::GameOver { .. } => self.screen = Screen::GameOver,
+                _ => {}
+            }
+        }
+    }
+
+    // every player's turns for the tick once they're all in, the rivals are worked out here like on every other peer
+    fn lockstep_turns(&mut self) -> Result<Option<Vec<Vec<Turn>>>, String> {
+        let Some(session) = &mut self.session else {
+            return Ok(None);
+        };
+        let tick = self.game.get_tick();
+        if session.lockstep.needs_input(tick) {
+            let turns = self.inputs[session.lockstep.get_player()].next_turns();
+            let message = session.lockstep.local_turns(tick, turns);
+            session.send(&message)?;
         }
-        let end_coord = self.snake.get_end();
-        if let Some(prev_apple_coord) = &self.prev_apple {
-            if end_coord == prev_apple_coord {
-                self.snake.grow(end_coord.clone());
-                self.prev_apple = None;
+        let Some(mut turns) = session.lockstep.take_turns(tick) else {
+            return Ok(None);
+        };
+        for (snake, controller) in self.controllers.iter_mut().enumerate() {
+            if let Some(controller) = controller {
+                turns.push(controller.next_turns(&self.game, snake));
             }
         }
+        Ok(Some(turns))
     }
 
-    fn add_holes(&mut self) {
-        let mut wall_coord = self.get_random_tile();
-        let mut counter = 0;
-        while counter < ADD_WALLS_NUM {
-            let mut rand_index = rand::thread_rng().gen_range(0..6);
-            let rand_dir = Direction::from_value(rand_index).unwrap();
-            let rand_neighbour_coord = wall_coord.move_in_dir(rand_dir);
-            if let Some(neighbour) = self.board.get_mut(&rand_neighbour_coord) {
-                if !neighbour.is_hole() && rand_neighbour_coord != self.apple && !self.snake.check_collision(&rand_neighbour_coord) {
-                    neighbour.set_as_hole();
-                    wall_coord = rand_neighbour_coord;
-                    counter = counter + 1;
+    fn tick(&mut self) {
+        // once it's over the snakes only fall, there's nothing more to agree on
+        let lockstep = self.session.is_some() && !self.game.is_over();
+        let was_over = self.game.is_over();
+        let turns = if self.playback.is_some() {
+            self.replay.all_turns_at(self.game.get_tick())
+        } else if lockstep {
+            match self.lockstep_turns() {
+                Ok(Some(turns)) => turns,
+                // waiting for someone's turns
+                Ok(None) => return,
+                Err(err) => {
+                    self.stop_online(err);
+                    return;
                 }
             }
+        } else {
+            ai::next_turns(&mut self.controllers, &mut self.inputs, &self.game)
+        };
+        if !self.game.is_over() && self.playback.is_none() {
+            for (player, player_turns) in turns.iter().enumerate() {
+                self.replay.record(self.game.get_tick(), player, player_turns);
+            }
+        }
+        self.game.step_players(&turns);
+        if let Some(session) = self.session.as_mut().filter(|_| lockstep) {
+            let sent = session.lockstep.stepped(&self.game).map_err(|desync| desync.to_string())
+                .and_then(|message| session.send(&message));
+            if let Err(err) = sent {
+                self.stop_online(err);
+                return;
+            }
+        }
+        if let Some(broadcast) = &self.broadcast {
+            broadcast.send(&ServerMessage::Tick { tick: self.game.get_tick() - 1, turns });
+            if self.game.get_tick().is_multiple_of(SNAPSHOT_INTERVAL) || (self.game.is_over() && !was_over) {
+                broadcast.send(&ServerMessage::Snapshot(Snapshot::of(&self.game)));
+            }
+            if self.game.is_over() && !was_over {
+                broadcast.send(&ServerMessage::GameOver { winner: self.game.get_winner(), scores: self.game.get_scores() });
+            }
+        }
+
+        if self.game.is_over() && self.screen == Screen::Playing {
+            self.screen = Screen::GameOver;
+            // the table is for playing alone, and by hand
+            if self.playback.is_none() && !self.autopilot && self.game.get_players() == 1 {
+                self.submit_score();
+            }
+        }
+        if self.game.is_over() && !self.replay_saved {
+            self.replay_saved = true;
+            let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+            let path = PathBuf::from(REPLAY_DIR).join(format!("{}-{}.replay", secs, self.replay.seed));
+            match self.replay.save(&path) {
+                Ok(()) => println!("replay saved to {}", path.display()),
+                Err(err) => eprintln!("could not save replay to {}: {}", path.display(), err)
+            }
         }
     }
 }
 
-impl EventHandler<ggez::GameError> for MainState {
-    fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+// the comings and goings on a hosted game or a broadcast, printed as they happen
+fn print_events() -> Sender<protocol::Event> {
+    let (events, received) = mpsc::channel();
+    thread::spawn(move || {
+        for event in received {
+            println!("{}", event);
+        }
+    });
+    events
+}
+
+fn remove_autosave() {
+    if let Some(path) = SavedGame::auto_path() {
+        if let Err(err) = std::fs::remove_file(&path) {
+            eprintln!("could not remove {}: {}", path.display(), err);
+        }
+    }
+}
+
+// every player is on autopilot, the results are printed game by game and summed up at the end
+fn run_headless(config: &GameConfig, fixed_seed: Option<u64>, games: u32) -> Result<(), String> {
+    let mut reports = Vec::new();
+    for game_index in 0..games {
+        let seed = fixed_seed.map_or_else(rand::random, |seed| seed.wrapping_add(game_index as u64));
+        let report = sim::simulate(config, seed, Bot::Autopilot, HEADLESS_MAX_TICKS).map_err(|err| err.to_string())?;
+        let outcome = report.outcome.name();
+        println!("seed {}: score {}, {} ticks, {} land tiles left, {}", seed, report.score, report.ticks, report.land_left, outcome);
+        reports.push(report);
+    }
+    let summary = Summary::new(&reports);
+    println!("{} games: average score {:.1}, best {}", summary.games, summary.average_score, summary.max_score);
+    Ok(())
+}
+
+// the hex under a point in the window, in pixels from its top left corner
+fn hex_at(ctx: &Context, x: f32, y: f32, side: f32) -> HexCoordinates {
+    let coords = graphics::screen_coordinates(ctx);
+    let (width, height) = graphics::drawable_size(ctx);
+    HexCoordinates::from_pixel(coords.x + x / width * coords.w, coords.y + y / height * coords.h, side)
+}
 
-        while timer::check_update_time(ctx, UPDATE_SPEED) {
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    seed: Option<u64>,
+    replay: Option<PathBuf>,
+    name: Option<String>,
+    config: Option<PathBuf>,
+    bindings: Option<PathBuf>,
+    overrides: Vec<(String, String)>,
+    difficulty: Option<Difficulty>,
+    autopilot: bool,
+    headless: bool,
+    games: u32,
+    connect: Option<String>,
+    host: bool,
+    port: u16,
+    join: Option<String>,
+    input_delay: u32,
+    watch: Option<String>,
+    // 1-based on the command line, like the snakes' names
+    follow: Option<usize>,
+    broadcast: Option<u16>,
+}
+
+impl Default for Args {
+    fn default() -> Args {
+        Args { seed: None, replay: None, name: None, config: None, bindings: None, overrides: Vec::new(), difficulty: None,
+            autopilot: false, headless: false, games: 1, connect: None, host: false, port: DEFAULT_PORT, join: None,
+            input_delay: lockstep::DEFAULT_INPUT_DELAY, watch: None, follow: None, broadcast: None }
+    }
+}
 
-            if self.end_game {
-                if self.snake.falling{
-                    self.snake.move_();
+impl Args {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().ok_or("--seed expects a value")?;
+                    let seed = value.parse().map_err(|_| format!("invalid seed: {}", value))?;
+                    parsed.seed = Some(seed);
+                }
+                "--name" => {
+                    let value = args.next().ok_or("--name expects a value")?;
+                    parsed.name = Some(value);
+                }
+                "--replay" => {
+                    let value = args.next().ok_or("--replay expects a file")?;
+                    parsed.replay = Some(PathBuf::from(value));
+                }
+                "--config" => {
+                    let value = args.next().ok_or("--config expects a file")?;
+                    parsed.config = Some(PathBuf::from(value));
+                }
+                "--bindings" => {
+                    let value = args.next().ok_or("--bindings expects a file")?;
+                    parsed.bindings = Some(PathBuf::from(value));
+                }
+                "--autopilot" => parsed.autopilot = true,
+                "--connect" => {
+                    let value = args.next().ok_or("--connect expects a server address")?;
+                    parsed.connect = Some(with_port(value));
+                }
+                "--host" => parsed.host = true,
+                "--port" => {
+                    let value = args.next().ok_or("--port expects a number")?;
+                    parsed.port = value.parse().map_err(|_| format!("invalid port: {}", value))?;
+                }
+                "--join" => {
+                    let value = args.next().ok_or("--join expects the host's address")?;
+                    parsed.join = Some(with_port(value));
+                }
+                "--input-delay" => {
+                    let value = args.next().ok_or("--input-delay expects a number of ticks")?;
+                    parsed.input_delay = value.parse().map_err(|_| format!("invalid input delay: {}", value))?;
+                }
+                "--watch" => {
+                    let value = args.next().ok_or("--watch expects a server address")?;
+                    parsed.watch = Some(with_port(value));
+                }
+                "--follow" => {
+                    let value = args.next().ok_or("--follow expects a snake's number")?;
+                    let snake: usize = value.parse().map_err(|_| format!("invalid snake: {}", value))?;
+                    parsed.follow = Some(snake.checked_sub(1).ok_or("snakes are numbered from 1")?);
+                }
+                "--broadcast" => {
+                    let value = args.next().ok_or("--broadcast expects a port")?;
+                    parsed.broadcast = Some(value.parse().map_err(|_| format!("invalid port: {}", value))?);
+                }
+                "--headless" => parsed.headless = true,
+                "--games" => {
+                    let value = args.next().ok_or("--games expects a number")?;
+                    parsed.games = value.parse().map_err(|_| format!("invalid number of games: {}", value))?;
+                }
+                "--difficulty" => {
+                    let value = args.next().ok_or("--difficulty expects easy, normal or hard")?;
+                    parsed.difficulty = Some(value.parse()?);
+                }
+                "--hex-side" | "--radius" | "--holes-num" | "--holes-interval" | "--tick-rate" | "--speed-curve" | "--speed-by"
+                | "--level" | "--island" | "--controls" | "--players"
+                | "--rivals" | "--rival-difficulty" => {
+                    let value = args.next().ok_or(format!("{} expects a value", arg))?;
+                    parsed.overrides.push((arg[2..].replace('-', "_"), value));
                 }
-                return Ok(());
+                _ => return Err(format!("unknown argument: {}", arg))
             }
+        }
+        // the game being watched is already someone else's to broadcast
+        if parsed.broadcast.is_some() && (parsed.connect.is_some() || parsed.watch.is_some()) {
+            return Err("--broadcast is for games played here, not on a server".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+fn with_port(addr: String) -> String {
+    if addr.contains(':') { addr } else { format!("{}:{}", addr, DEFAULT_PORT) }
+}
+
+impl EventHandler<ggez::GameError> for MainState {
+    fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        if self.screen == Screen::Playing {
+            self.play_time += timer::delta(ctx);
+        }
+        if self.net.is_some() {
+            self.receive();
+            return Ok(());
+        }
+        let received = match &mut self.session {
+            Some(session) if self.net_error.is_none() && !self.game.is_over() => session.receive(),
+            _ => Ok(())
+        };
+        if let Err(err) = received {
+            self.stop_online(err);
+        }
 
-            self.snake.move_();
-            if self.snake.has_eaten_itself() {
-                self.end_game = true;
-                return Ok(());
+        let speed = match &self.playback {
+            Some(playback) if playback.fast_forward => self.game.get_tick_rate() * FAST_FORWARD,
+            _ => self.game.get_tick_rate()
+        };
+        while timer::check_update_time(ctx, speed) {
+            if self.screen.is_running() && self.net_error.is_none() && !matches!(&self.playback, Some(playback) if playback.paused) {
+                self.tick();
+            }
+        }
+        if let Some(playback) = &mut self.playback {
+            if playback.step && self.screen.is_running() {
+                playback.step = false;
+                self.tick();
             }
-            self.check_if_falls();
-            self.check_if_eaten_apple();
         }
         Ok(())
     }
@@ -144,43 +802,309 @@ impl EventHandler<ggez::GameError> for MainState {
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         graphics::clear(ctx, Color::BLACK);
 
-        for (_, tile) in &self.board {
-            tile.draw(ctx)?;
+        let side = self.config.hex_side;
+        let screen = self.config.screen_size(self.game.get_radius());
+        // a spectator following a snake sees the island around its head, up close
+        if let Some(player) = self.followed().filter(|&player| self.game.is_alive(player)) {
+            let (x, y) = self.game.get_snakes().nth(player).unwrap().get_head().get_eucl_center(side);
+            let half = screen / FOLLOW_ZOOM;
+            graphics::set_screen_coordinates(ctx, Rect::new(x - half, y - half, half * 2.0, half * 2.0))?;
+        }
+        for (coord, tile) in self.game.get_board() {
+            render::draw_tile(ctx, coord, tile, side)?;
         }
 
 
-        let (apple_x, apple_y) = self.apple.get_eucl_center(HEX_SIDE);
-        let apple = Mesh::new_circle(ctx, DrawMode::fill(), Point2::from([apple_x, apple_y]), 10.0, 0.2, Color::RED)?;
-        graphics::draw(ctx, &apple, (glam::Vec2::new(0.0, 0.0), ))?;
-
+        render::draw_apple(ctx, self.game.get_apple(), side)?;
 
-        self.snake.draw(ctx)?;
 
+        for (player, snake) in self.game.get_snakes().enumerate() {
+            let dead = !self.game.is_alive(player) && !snake.falling && self.game.get_players() > 1;
+            let color = if dead { render::DEAD_COLOR } else { render::PLAYER_COLORS[player] };
+            render::draw_snake(ctx, snake, side, color)?;
+        }
+        graphics::set_screen_coordinates(ctx, Rect::new(-screen, -screen, screen * 2.0, screen * 2.0))?;
 
-        let screen = ((GRID_RADIUS as f32) * 2.0 + 1.0) * 3_f32.sqrt() / 2_f32 * HEX_SIDE;
-        let mut score_text = Text::new(self.score.to_string());
-        score_text.set_font(graphics::Font::default(), graphics::PxScale { x: 50.0, y: 50.0 });
-        score_text.draw(ctx, DrawParam::from((glam::Vec2::new(screen - 100.0, -screen + 100.0), )))?;
+        for (player, score) in self.game.get_scores().into_iter().enumerate() {
+            let mut score_text = Text::new(score.to_string());
+            score_text.set_font(graphics::Font::default(), graphics::PxScale { x: 50.0, y: 50.0 });
+            let color = if self.game.get_players() > 1 { render::PLAYER_COLORS[player] } else { Color::WHITE };
+            let position = glam::Vec2::new(screen - 100.0, -screen + 100.0 + player as f32 * 50.0);
+            score_text.draw(ctx, DrawParam::from((position, )).color(color))?;
+        }
 
-        if self.end_game {
-            let mut end_text = Text::new("GAME OVER");
+        if self.screen != Screen::Playing {
+            render::draw_shade(ctx, screen)?;
+        }
+        match self.screen {
+            Screen::Title => {
+                render::draw_centered_text(ctx, "SNAKE", -100.0, 100.0, Color::GREEN)?;
+                render::draw_centered_text(ctx, "press Enter to start", 20.0, 40.0, Color::WHITE)?;
+                let difficulty = self.difficulty.map_or("as configured", |difficulty| difficulty.name());
+                render::draw_centered_text(ctx, &format!("< speed: {} >", difficulty), 80.0, 30.0, Color::WHITE)?;
+                render::draw_centered_text(ctx, &format!("C - controls: {}", self.config.controls), 120.0, 30.0, Color::WHITE)?;
+                if let Some(saved) = &self.resume {
+                    let line = format!("R - resume last game, score {} at {}:{:02}", saved.game.get_score(),
+                                       saved.play_time_secs / 60, saved.play_time_secs % 60);
+                    render::draw_centered_text(ctx, &line, 160.0, 30.0, Color::YELLOW)?;
+                }
+            }
+            Screen::Paused => {
+                render::draw_centered_text(ctx, "PAUSED", -100.0, 100.0, Color::WHITE)?;
+                render::draw_centered_text(ctx, "P to resume, Q for the title screen", 20.0, 40.0, Color::WHITE)?;
+            }
+            Screen::GameOver => {
+                render::draw_centered_text(ctx, "GAME OVER", -100.0, 100.0, Color::RED)?;
+                let hint = if self.is_online() { "press Escape to quit" } else { "press R to restart" };
+                render::draw_centered_text(ctx, hint, 20.0, 40.0, Color::WHITE)?;
+                if let Some(err) = &self.net_error {
+                    for (line, text) in err.lines().enumerate() {
+                        render::draw_centered_text(ctx, text.trim(), 90.0 + line as f32 * 30.0, 26.0, Color::RED)?;
+                    }
+                } else if self.game.get_players() > 1 {
+                    match self.game.get_winner() {
+                        Some(winner) => render::draw_centered_text(ctx, &format!("{} WINS", self.snake_name(winner)), 90.0, 50.0,
+                                                                   render::PLAYER_COLORS[winner])?,
+                        None => render::draw_centered_text(ctx, "DRAW", 90.0, 50.0, Color::WHITE)?,
+                    }
+                } else if self.playback.is_none() {
+                    let table = self.highscores.table(&self.config.board_settings());
+                    for (place, entry) in table.iter().enumerate() {
+                        let line = format!("{:>2}. {:<12} {:>4} {:>4} {:>3}:{:02}", place + 1, entry.name, entry.score,
+                                           entry.length, entry.duration_secs / 60, entry.duration_secs % 60);
+                        let color = if self.highscore_place == Some(place) { Color::YELLOW } else { Color::WHITE };
+                        render::draw_centered_text(ctx, &line, 90.0 + place as f32 * 30.0, 26.0, color)?;
+                    }
+                }
+            }
+            Screen::Playing => {}
+        }
 
-            end_text.set_font(graphics::Font::default(), graphics::PxScale { x: 100.0, y: 100.0 });
-            end_text.draw(ctx, DrawParam::from((glam::Vec2::new(-250.0, -100.0), Color::RED, )))?;
+        if self.autopilot && self.playback.is_none() {
+            let mut autopilot_text = Text::new("AUTOPILOT");
+            autopilot_text.set_font(graphics::Font::default(), graphics::PxScale { x: 30.0, y: 30.0 });
+            autopilot_text.draw(ctx, DrawParam::from((glam::Vec2::new(-screen + 20.0, -screen + 20.0), )))?;
+        }
+        if self.is_watching() {
+            let (label, color) = match self.followed() {
+                Some(player) => (format!("WATCHING  {}", self.snake_name(player)), render::PLAYER_COLORS[player]),
+                None => ("WATCHING".to_string(), Color::WHITE)
+            };
+            let mut watching_text = Text::new(label);
+            watching_text.set_font(graphics::Font::default(), graphics::PxScale { x: 30.0, y: 30.0 });
+            watching_text.draw(ctx, DrawParam::from((glam::Vec2::new(-screen + 20.0, -screen + 20.0), )).color(color))?;
+        } else if self.is_online() {
+            let player = self.own_player();
+            let mut online_text = Text::new(format!("ONLINE  {}", self.snake_name(player)));
+            online_text.set_font(graphics::Font::default(), graphics::PxScale { x: 30.0, y: 30.0 });
+            let color = render::PLAYER_COLORS[player];
+            online_text.draw(ctx, DrawParam::from((glam::Vec2::new(-screen + 20.0, -screen + 20.0), )).color(color))?;
+        }
+        if let Some((notice, since)) = &self.notice {
+            if since.elapsed() < NOTICE_TIME {
+                let mut notice_text = Text::new(notice.as_str());
+                notice_text.set_font(graphics::Font::default(), graphics::PxScale { x: 30.0, y: 30.0 });
+                notice_text.draw(ctx, DrawParam::from((glam::Vec2::new(-screen + 20.0, screen - 50.0), )))?;
+            }
+        }
+        if let Some(playback) = &self.playback {
+            let state = if playback.paused { "PAUSED" } else if playback.fast_forward { ">>" } else { ">" };
+            let mut replay_text = Text::new(format!("REPLAY {}  tick {}", state, self.game.get_tick()));
+            replay_text.set_font(graphics::Font::default(), graphics::PxScale { x: 30.0, y: 30.0 });
+            replay_text.draw(ctx, DrawParam::from((glam::Vec2::new(-screen + 20.0, -screen + 20.0), )))?;
         }
 
         graphics::present(ctx)?;
         Ok(())
     }
 
+    // a game left open is kept for next time
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        if self.can_save() && self.save(SavedGame::auto_path()) {
+            println!("saved the game to resume next time");
+        }
+        false
+    }
+
     fn key_down_event(&mut self,
-                      _ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
-        self.snake.rotate_head(keycode);
+                      ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
+        let actions = self.bindings.key_actions(keycode);
+        self.on_actions(ctx, 0, &actions);
+        for (player, action) in self.bindings.other_player_actions(keycode) {
+            self.on_actions(ctx, player, &[action]);
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        let player = self.gamepad_player(id);
+        let actions = self.bindings.button_actions(button);
+        self.on_actions(ctx, player, &actions);
+    }
+
+    // the stick turns the snake once whenever it's pushed somewhere new
+    fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        let player = self.gamepad_player(id);
+        let (_, stick, last_turn) = &mut self.gamepads[player];
+        match axis {
+            Axis::LeftStickX => stick.0 = value,
+            Axis::LeftStickY => stick.1 = value,
+            _ => return
+        }
+        let turn = bindings::stick_turn(stick.0, stick.1, self.config.controls);
+        if turn != *last_turn {
+            *last_turn = turn;
+            if let Some(turn) = turn {
+                self.push_turn(player, turn);
+            }
+        }
+    }
+
+    // steers the head towards the clicked hex, taps come in as clicks where the platform turns them into ones
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if button != MouseButton::Left {
+            return;
+        }
+        let player = self.own_player();
+        // only a snake still going has a head to steer, one that fell is gone bit by bit
+        if self.screen != Screen::Playing || self.game.is_over() || player >= self.game.get_players() || !self.game.is_alive(player) {
+            return;
+        }
+        let snake = self.game.get_player_snake(player);
+        if snake.is_empty() {
+            return;
+        }
+        let target = hex_at(ctx, x, y, self.config.hex_side);
+        // the turns not applied yet already point the head elsewhere
+        let dir = self.inputs.get(player).into_iter().flat_map(|input| input.iter())
+            .fold(snake.get_dir(), |dir, turn| Direction::change_dir(dir, *turn));
+        if let Some(turn) = snake::turn_towards(snake.get_head(), dir, &target) {
+            self.push_turn(0, turn);
+        }
+    }
+}
+
+// the config file given with --config, or the one in the user's config directory if there is one
+fn load_config(args: &Args) -> Result<GameConfig, String> {
+    GameConfig::load_with_overrides(args.config.as_deref(), &args.overrides)
+}
+
+fn load_bindings(args: &Args) -> Result<Bindings, String> {
+    match args.bindings.clone().or_else(|| Bindings::default_path().filter(|path| path.exists())) {
+        Some(path) => Bindings::load(&path).map_err(|err| format!("could not load bindings {}: {}", path.display(), err)),
+        None => Ok(Bindings::default())
     }
 }
 
 fn main() {
-    let screen = ((GRID_RADIUS as f32) * 2.0 + 1.0) * 3_f32.sqrt() / 2_f32 * HEX_SIDE;
+    let args = match Args::parse(std::env::args()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("usage: snek [--seed <seed>] [--name <name>] [--replay <file>] [--config <file>] [--bindings <file>]");
+            eprintln!("            [--radius <n>] [--holes-num <n>] [--holes-interval <n>] [--tick-rate <n>] [--hex-side <px>]");
+            eprintln!("            [--difficulty <easy|normal|hard>] [--speed-curve <curve>] [--speed-by <score|length>]");
+            eprintln!("            [--level <file>] [--island hexagon|generated] [--controls relative|absolute]");
+            eprintln!("            [--players <1-4>] [--rivals <n>] [--rival-difficulty <easy|normal|hard>]");
+            eprintln!("            [--autopilot] [--headless [--games <n>]] [--connect <host[:port]>]");
+            eprintln!("            [--host [--port <port>] [--input-delay <ticks>]] [--join <host[:port]>]");
+            eprintln!("            [--watch <host[:port]> [--follow <n>]] [--broadcast <port>]");
+            std::process::exit(2);
+        }
+    };
+    if args.headless {
+        let result = load_config(&args).and_then(|mut config| {
+            if let Some(difficulty) = args.difficulty {
+                difficulty.apply(&mut config);
+            }
+            run_headless(&config, args.seed, args.games)
+        });
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let replay = args.replay.as_ref().map(|path| match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(err) => {
+            eprintln!("could not load replay {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    });
+    let (config, bindings) = match load_config(&args).and_then(|config| Ok((config, load_bindings(&args)?))) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let name = args.name.clone().unwrap_or_else(highscore::default_name);
+    let main_state = if let Some(replay) = replay {
+        MainState::from_replay(replay, config.hex_side)
+    } else if let Some(addr) = &args.connect {
+        println!("waiting for the game on {} to start", addr);
+        NetClient::connect(addr.as_str(), &name)
+            .map(|(net, level, game)| MainState { net: Some(net), ..MainState::online(level, game, &config, name) })
+            .map_err(|err| GameError::CustomError(format!("could not join {}: {}", addr, err)))
+    } else if let Some(addr) = &args.watch {
+        println!("waiting for a game on {} to watch", addr);
+        NetClient::watch(addr.as_str(), &name)
+            .map(|(net, level, game)| MainState { net: Some(net), follow: args.follow, ..MainState::online(level, game, &config, name) })
+            .map_err(|err| GameError::CustomError(format!("could not watch {}: {}", addr, err)))
+    } else if args.host || args.join.is_some() {
+        let mut game_config = config.clone();
+        if let Some(difficulty) = args.difficulty {
+            difficulty.apply(&mut game_config);
+        }
+        let started = match &args.join {
+            Some(addr) => {
+                println!("waiting for the host on {} to start the game", addr);
+                Session::join(addr.as_str(), &name)
+            }
+            None => {
+                let seed = args.seed.unwrap_or_else(rand::random);
+                Session::host(("0.0.0.0", args.port), &game_config, &name, seed, args.input_delay, &print_events())
+            }
+        };
+        started.map(|(session, level, game)| MainState { session: Some(session), ..MainState::online(level, game, &config, name) })
+            .map_err(|err| GameError::CustomError(format!("could not start the game: {}", err)))
+    } else {
+        MainState::new(config, args.difficulty, args.seed, name)
+    };
+    let mut main_state = match main_state {
+        Ok(main_state) => main_state,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    main_state.bindings = bindings;
+    main_state.autopilot = args.autopilot && !main_state.is_online();
+    // a game left open last time is offered instead of being played over
+    if main_state.screen == Screen::Title {
+        if let Some(path) = SavedGame::auto_path().filter(|path| path.exists()) {
+            match SavedGame::load(&path) {
+                Ok(saved) => main_state.resume = Some(saved),
+                Err(err) => eprintln!("could not load the game left open last time from {}: {}", path.display(), err)
+            }
+        }
+    }
+    if let Some(port) = args.broadcast {
+        match Broadcast::listen(("0.0.0.0", port), print_events()) {
+            Ok((broadcast, addr)) => {
+                println!("spectators can watch on {}", addr);
+                main_state.broadcast = Some(broadcast);
+                main_state.start_broadcast();
+            }
+            Err(err) => {
+                eprintln!("could not listen on port {}: {}", port, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let screen = main_state.config.screen_size(main_state.game.get_radius());
     let conf = Conf::new().window_mode(WindowMode {
         width: screen * 2.0,
         height: screen * 2.0,
@@ -202,9 +1126,7 @@ fn main() {
         .build().
         unwrap();
 
-    graphics::set_screen_coordinates(&mut ctx, Rect::new(-screen, -screen, screen * 2.0, screen * 2.0));
-
-    let main_state = MainState::new().unwrap();
+    graphics::set_screen_coordinates(&mut ctx, Rect::new(-screen, -screen, screen * 2.0, screen * 2.0)).unwrap();
 
     event::run(ctx, event_loop, main_state)
 }
@@ -214,23 +1136,37 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_random_tile() {
-        let game = MainState::new().unwrap();
-        let random_tile_coord = game.get_random_tile();
-        let random_tile = game.board.get(&random_tile_coord).unwrap();
-        assert!(!random_tile.is_hole())
-    }
-
-    #[test]
-    fn test_add_walls(){
-        let mut game = MainState::new().unwrap();
-        game.add_holes();
-        let mut counter = 0;
-        for (coord, tile) in game.board{
-            if tile.is_hole() &&  !(coord.r.abs() == GRID_RADIUS || coord.b.abs() == GRID_RADIUS || coord.g.abs() == GRID_RADIUS){
-                counter = counter + 1;
-            }
-        }
-        assert_eq!(counter, ADD_WALLS_NUM);
+    fn test_parse_args() {
+        let args = |list: &[&str]| Args::parse(list.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&["snek"]), Ok(Args::default()));
+        assert_eq!(args(&["snek", "--seed", "1234"]).unwrap().seed, Some(1234));
+        assert_eq!(args(&["snek", "--replay", "run.replay"]).unwrap().replay, Some(PathBuf::from("run.replay")));
+        assert!(args(&["snek", "--replay"]).is_err());
+        assert_eq!(args(&["snek", "--name", "edi"]).unwrap().name, Some("edi".to_string()));
+        assert_eq!(args(&["snek", "--radius", "6", "--tick-rate", "3"]).unwrap().overrides,
+                   vec![("radius".to_string(), "6".to_string()), ("tick_rate".to_string(), "3".to_string())]);
+        assert!(args(&["snek", "--radius"]).is_err());
+        assert_eq!(args(&["snek", "--difficulty", "hard"]).unwrap().difficulty, Some(Difficulty::Hard));
+        assert!(args(&["snek", "--difficulty", "brutal"]).is_err());
+        assert!(args(&["snek", "--seed"]).is_err());
+        assert!(args(&["snek", "--seed", "abc"]).is_err());
+        assert!(args(&["snek", "--colour"]).is_err());
+        let headless = args(&["snek", "--headless", "--games", "20"]).unwrap();
+        assert!(headless.headless && !headless.autopilot);
+        assert_eq!(headless.games, 20);
+        assert!(args(&["snek", "--autopilot"]).unwrap().autopilot);
+        assert!(args(&["snek", "--games", "many"]).is_err());
+        assert_eq!(args(&["snek", "--connect", "example.org"]).unwrap().connect, Some(format!("example.org:{}", DEFAULT_PORT)));
+        assert_eq!(args(&["snek", "--connect", "10.0.0.2:9000"]).unwrap().connect, Some("10.0.0.2:9000".to_string()));
+        let host = args(&["snek", "--host", "--port", "9000", "--input-delay", "5"]).unwrap();
+        assert!(host.host);
+        assert_eq!((host.port, host.input_delay), (9000, 5));
+        assert_eq!(args(&["snek", "--join", "peer"]).unwrap().join, Some(format!("peer:{}", DEFAULT_PORT)));
+        assert!(args(&["snek", "--input-delay", "-1"]).is_err());
+        let watch = args(&["snek", "--watch", "example.org", "--follow", "2"]).unwrap();
+        assert_eq!((watch.watch, watch.follow), (Some(format!("example.org:{}", DEFAULT_PORT)), Some(1)));
+        assert!(args(&["snek", "--follow", "0"]).is_err());
+        assert_eq!(args(&["snek", "--broadcast", "9000"]).unwrap().broadcast, Some(9000));
+        assert!(args(&["snek", "--broadcast", "9000", "--watch", "example.org"]).is_err());
     }
 }
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::config::GameConfig;
+use crate::direction::Turn;
+use crate::game::GameState;
+use crate::hexagon::HexCoordinates;
+use crate::level::Level;
+
+// bumped whenever a message changes, a server only plays with clients of the same version
+pub const PROTOCOL_VERSION: u32 = 2;
+pub const DEFAULT_PORT: u16 = 7878;
+// far more than the biggest welcome or snapshot, a line longer than this is from something that isn't a peer
+pub const MAX_LINE: usize = 1 << 20;
+// a client that connects has this long to say who it is
+pub const GREETING_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Every message is a JSON object on a line of its own, its "type" says which one it is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Join { version: u32, name: String },
+    // to follow the game without playing in it, joining at any time
+    Watch { version: u32, name: String },
+    // turns for the server to queue up for the client's snake, applied one per tick
+    Input { turns: Vec<Turn> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    // the game is starting with everything needed to build it the same way the server did;
+    // the level is the level file's text, without one the island comes from the config and the seed;
+    // the player is the client's snake, None for spectators
+    Welcome { version: u32, player: Option<usize>, names: Vec<String>, seed: u64, config: GameConfig, level: Option<String> },
+    // the turns every snake made on the tick, in player order
+    Tick { tick: u32, turns: Vec<Vec<Turn>> },
+    // the whole state after the given tick, every now and then to check the steps against
+    Snapshot(Snapshot),
+    GameOver { winner: Option<usize>, scores: Vec<i32> },
+    Error { message: String },
+}
+
+// What happens to the connections of a server, a broadcast or a lockstep host, sent to whoever's running it to show.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    // the host is waiting for this many more players
+    Waiting { addr: SocketAddr, players: usize },
+    WantsToPlay { name: String, addr: SocketAddr },
+    Joined { name: String, player: usize },
+    Left { name: String },
+    Watching { name: String, addr: SocketAddr },
+    StoppedWatching { name: String },
+    TurnedAway { addr: SocketAddr, reason: String },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Waiting { addr, players } => write!(f, "waiting on {} for {} more players", addr, players),
+            Event::WantsToPlay { name, addr } => write!(f, "{} wants to play from {}", name, addr),
+            Event::Joined { name, player } => write!(f, "{} is player {}", name, player + 1),
+            Event::Left { name } => write!(f, "{} left", name),
+            Event::Watching { name, addr } => write!(f, "{} is watching from {}", name, addr),
+            Event::StoppedWatching { name } => write!(f, "{} stopped watching", name),
+            Event::TurnedAway { addr, reason } => write!(f, "turned away {}: {}", addr, reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub apple: HexCoordinates,
+    pub holes: Vec<HexCoordinates>,
+    // every snake from its head to its end
+    pub snakes: Vec<Vec<HexCoordinates>>,
+    pub scores: Vec<i32>,
+    pub alive: Vec<bool>,
+}
+
+impl Snapshot {
+    pub fn of(game: &GameState) -> Snapshot {
+        Snapshot {
+            tick: game.get_tick(),
+            apple: game.get_apple().clone(),
+            holes: game.get_board().iter().filter(|(_, tile)| tile.is_hole()).map(|(coord, _)| coord.clone()).collect(),
+            snakes: game.get_snakes().map(|snake| snake.body().map(|part| part.coordinates.clone()).collect()).collect(),
+            scores: game.get_scores(),
+            alive: (0..game.get_players()).map(|player| game.is_alive(player)).collect(),
+        }
+    }
+}
+
+// the other side may not have the level file, so it gets what's in it
+pub fn level_text(config: &GameConfig) -> io::Result<Option<String>> {
+    match &config.level {
+        Some(path) => fs::read_to_string(path).map(Some),
+        None => Ok(None)
+    }
+}
+
+// the game the other side started, from the level text it sent or else the config's island for the seed;
+// the config came over the wire, so it's checked like one read from a file
+pub fn start_game(config: &GameConfig, seed: u64, level: Option<&str>) -> io::Result<(Level, GameState)> {
+    config.validate().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let level = match level {
+        Some(text) => Level::parse(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+        None => config.island_level(seed)
+    };
+    level.spawns(config.snakes()).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let game = GameState::from_level(config, &level, seed);
+    Ok((level, game))
+}
+
+// a TCP stream carrying messages one line each way
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nodelay(true)?;
+        Ok(Connection { reader: BufReader::new(stream.try_clone()?), writer: stream })
+    }
+
+    // a second handle on the same stream, so one thread can wait for messages while another sends them
+    pub fn try_clone(&self) -> io::Result<Connection> {
+        Connection::new(self.writer.try_clone()?)
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.writer.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.writer.set_write_timeout(timeout)
+    }
+
+    pub fn send<T: Serialize>(&mut self, message: &T) -> io::Result<()> {
+        let mut line = serde_json::to_string(message).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())
+    }
+
+    // None once the other side has closed the connection
+    pub fn receive<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>> {
+        let mut line = String::new();
+        let read = self.reader.by_ref().take(MAX_LINE as u64 + 1).read_line(&mut line)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read > MAX_LINE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("a message longer than {} bytes", MAX_LINE)));
+        }
+        serde_json::from_str(&line).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+// The first message on a connection just taken, given GREETING_TIMEOUT to come. The connection comes back either way,
+// so the one that's turned away can be told why.
+pub fn greeting<T: DeserializeOwned>(stream: TcpStream) -> io::Result<(Connection, io::Result<T>)> {
+    stream.set_read_timeout(Some(GREETING_TIMEOUT))?;
+    let mut connection = Connection::new(stream)?;
+    let greeting = connection.receive().and_then(|message| {
+        message.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "closed the connection without a word"))
+    });
+    connection.set_read_timeout(None)?;
+    Ok((connection, greeting))
+}
+
+// a thread per connection waiting for its messages, they come out tagged with the connection's index; None when it's gone
+pub fn read_messages<'a, T: DeserializeOwned + Send + 'static>(connections: impl Iterator<Item = &'a Connection>)
+                                                                -> io::Result<Receiver<(usize, Option<T>)>> {
+    let (sender, receiver) = mpsc::channel();
+    for (index, connection) in connections.enumerate() {
+        let mut reader = connection.try_clone()?;
+        let sender = sender.clone();
+        thread::spawn(move || loop {
+            match reader.receive() {
+                Ok(Some(message)) => {
+                    if sender.send((index, Some(message))).is_err() {
+                        break;
+                    }
+                }
+                _ => {
+                    let _ = sender.send((index, None));
+                    break;
+                }
+            }
+        });
+    }
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direction::Direction;
+
+    #[test]
+    fn test_messages() {
+        let input = ClientMessage::Input { turns: vec![Turn::Left, Turn::Towards(Direction::East)] };
+        let text = serde_json::to_string(&input).unwrap();
+        assert_eq!(text, r#"{"type":"input","turns":["L","East"]}"#);
+        assert_eq!(serde_json::from_str::<ClientMessage>(&text).unwrap(), input);
+
+        let game = GameState::new(&GameConfig::default(), 3);
+        let snapshot = ServerMessage::Snapshot(Snapshot::of(&game));
+        let text = serde_json::to_string(&snapshot).unwrap();
+        assert!(text.starts_with(r#"{"type":"snapshot","tick":0"#));
+        assert_eq!(serde_json::from_str::<ServerMessage>(&text).unwrap(), snapshot);
+        assert!(serde_json::from_str::<ServerMessage>(r#"{"type":"teleport"}"#).is_err());
+    }
+
+    #[test]
+    fn test_greeting() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut client = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        client.send(&ClientMessage::Watch { version: PROTOCOL_VERSION, name: "fan".to_string() }).unwrap();
+        let (_, watch) = greeting::<ClientMessage>(listener.accept().unwrap().0).unwrap();
+        assert!(matches!(watch, Ok(ClientMessage::Watch { name, .. }) if name == "fan"));
+
+        drop(TcpStream::connect(addr).unwrap());
+        let (_, closed) = greeting::<ClientMessage>(listener.accept().unwrap().0).unwrap();
+        assert_eq!(closed.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_line_too_long() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut connection = Connection::new(listener.accept().unwrap().0).unwrap();
+        // the line never ends, it's turned down once it's too long rather than read on and on
+        let writer = std::thread::spawn(move || {
+            let _ = stream.write_all(&vec![b' '; MAX_LINE * 2]);
+        });
+        assert_eq!(connection.receive::<ClientMessage>().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        drop(connection);
+        writer.join().unwrap();
+    }
+}
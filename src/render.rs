@@ -0,0 +1,131 @@
+use ggez::{Context, GameResult, graphics};
+use ggez::graphics::{Color, Drawable, DrawMode, DrawParam, Mesh, Rect, Text};
+use ggez::mint::Point2;
+use snek::direction::Direction;
+use snek::hexagon::HexCoordinates;
+use snek::snake::Snake;
+use snek::tile::Tile;
+
+pub const PLAYER_COLORS: [Color; 4] = [
+    Color { r: 13.0 / 255.0, g: 133.0 / 255.0, b: 31.0 / 255.0, a: 1.0 },
+    Color { r: 40.0 / 255.0, g: 90.0 / 255.0, b: 200.0 / 255.0, a: 1.0 },
+    Color { r: 210.0 / 255.0, g: 120.0 / 255.0, b: 20.0 / 255.0, a: 1.0 },
+    Color { r: 160.0 / 255.0, g: 40.0 / 255.0, b: 160.0 / 255.0, a: 1.0 },
+];
+pub const DEAD_COLOR: Color = Color { r: 0.35, g: 0.35, b: 0.35, a: 1.0 };
+
+pub struct Hexagon {
+    pub vertices: [Point2<f32>; 6],
+}
+
+impl Hexagon {
+    pub fn new(coord: &HexCoordinates, side: f32) -> Hexagon {
+        let (center_x, center_y) = coord.get_eucl_center(side);
+
+        let sin = 3_f32.sqrt() / 2_f32;
+        let vertices = [
+            Point2::from([center_x, center_y + side]),
+            Point2::from([center_x + sin * side, center_y + side / 2_f32]),
+            Point2::from([center_x + sin * side, center_y - side / 2_f32]),
+            Point2::from([center_x, center_y - side]),
+            Point2::from([center_x - sin * side, center_y - side / 2_f32]),
+            Point2::from([center_x - sin * side, center_y + side / 2_f32])
+        ];
+        Hexagon { vertices }
+    }
+}
+
+pub fn get_vertices(dir: Direction, side: f32) -> (Point2<f32>, Point2<f32>) {
+    let sin = 3_f32.sqrt() / 2_f32;
+    let vertices = [
+        Point2::from([-sin * side, side / 2_f32]),
+        Point2::from([-sin * side, -side / 2_f32]),
+        Point2::from([0.0, -side]),
+        Point2::from([sin * side, -side / 2_f32]),
+        Point2::from([sin * side, side / 2_f32]),
+        Point2::from([0.0, side])
+    ];
+    let val = dir.value() as usize;
+    (vertices[val], vertices[(val + 1).rem_euclid(6)])
+}
+
+pub fn draw_tile(ctx: &mut Context, coord: &HexCoordinates, tile: &Tile, side: f32) -> GameResult<()> {
+    let hex = Hexagon::new(coord, side);
+    let color = if tile.is_hole() { Color::BLACK } else { Color::from_rgb(199, 154, 18) };
+    let hex_mesh_fill = graphics::Mesh::new_polygon(
+        ctx,
+        graphics::DrawMode::fill(),
+        &hex.vertices,
+        color,
+    )?;
+
+    let hex_mesh_stroke = graphics::Mesh::new_polygon(
+        ctx,
+        graphics::DrawMode::stroke(2.0),
+        &hex.vertices,
+        Color::BLACK ,
+    )?;
+
+
+    graphics::draw(ctx, &hex_mesh_stroke, (glam::Vec2::new(0.0, 0.0), ))?;
+    graphics::draw(ctx, &hex_mesh_fill, (glam::Vec2::new(0.0, 0.0), ))
+}
+
+pub fn draw_apple(ctx: &mut Context, coord: &HexCoordinates, side: f32) -> GameResult<()> {
+    let (apple_x, apple_y) = coord.get_eucl_center(side);
+    let apple = Mesh::new_circle(ctx, DrawMode::fill(), Point2::from([apple_x, apple_y]), side / 3.0, 0.2, Color::RED)?;
+    graphics::draw(ctx, &apple, (glam::Vec2::new(0.0, 0.0), ))
+}
+
+pub fn draw_snake(ctx: &mut Context, snake: &Snake, side: f32, color: Color) -> GameResult<()> {
+    //draw head
+    if !snake.is_empty() {
+        let mut start_range = 0;
+        if !snake.falling {
+            start_range = 1;
+            let head = snake.body().next().unwrap();
+            let (head_center_x, head_center_y) = head.coordinates.get_eucl_center(side);
+            let (point_from_1, point_from_2) = get_vertices(head.dir_from, side);
+            let (point_to_1, point_to_2) = get_vertices(head.dir_to, side);
+
+            let head_mesh = graphics::Mesh::new_polygon(ctx, DrawMode::fill(), &[
+                point_from_1,
+                Point2::from([(point_to_1.x + point_to_2.x) / 2_f32, (point_to_1.y + point_to_2.y) / 2_f32]),
+                point_from_2
+            ], color)?;
+
+
+            head_mesh.draw(ctx, DrawParam::default().dest(Point2::from([head_center_x, head_center_y])))?;
+        }
+
+
+        //draw tail
+        for tail_part in snake.body().skip(start_range) {
+            let (center_x, center_y) = tail_part.coordinates.get_eucl_center(side);
+            let (point_from_1, point_from_2) = get_vertices(tail_part.dir_from, side);
+            let (point_to_1, point_to_2) = get_vertices(tail_part.dir_to, side);
+            let tail_part_mesh = graphics::Mesh::new_polygon(ctx, DrawMode::fill(), &[
+                point_from_1,
+                point_from_2,
+                point_to_1,
+                point_to_2
+            ], color)?;
+            tail_part_mesh.draw(ctx, DrawParam::default().dest(Point2::from([center_x, center_y])))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn draw_centered_text(ctx: &mut Context, text: &str, y: f32, size: f32, color: Color) -> GameResult<()> {
+    let mut text = Text::new(text);
+    text.set_font(graphics::Font::default(), graphics::PxScale { x: size, y: size });
+    let width = text.width(ctx);
+    text.draw(ctx, DrawParam::from((glam::Vec2::new(-width / 2.0, y), color, )))
+}
+
+pub fn draw_shade(ctx: &mut Context, screen: f32) -> GameResult<()> {
+    let rect = Rect::new(-screen, -screen, screen * 2.0, screen * 2.0);
+    let shade = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, Color::new(0.0, 0.0, 0.0, 0.6))?;
+    graphics::draw(ctx, &shade, (glam::Vec2::new(0.0, 0.0), ))
+}
@@ -0,0 +1,183 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::config::GameConfig;
+use crate::direction::Turn;
+
+// version 2 has more than one snake; a version 1 replay has only the one, which reads the same
+const REPLAY_HEADER: &str = "snek-replay 2";
+const OLD_HEADERS: [&str; 1] = ["snek-replay 1"];
+
+// the options that change the game's rules, hex_side only changes how it looks
+// and the rivals' turns are recorded like the players', so how they think doesn't matter
+const REPLAY_OPTIONS: [&str; 10] = ["radius", "holes_num", "holes_interval", "tick_rate", "speed_curve", "speed_by", "island", "players", "rivals",
+    "level"];
+
+// everything needed to play a game again: the seed, the rules and every turn with the tick it was applied on
+// and the player who made it; the first player's turns are written as "<tick> <turn>", the others' as "<tick> <player>:<turn>"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: GameConfig,
+    pub turns: Vec<(u32, usize, Turn)>,
+}
+
+impl Replay {
+    pub fn new(config: &GameConfig, seed: u64) -> Replay {
+        Replay { seed, config: config.clone(), turns: Vec::new() }
+    }
+
+    pub fn record(&mut self, tick: u32, player: usize, turns: &[Turn]) {
+        for turn in turns {
+            self.turns.push((tick, player, *turn));
+        }
+    }
+
+    pub fn turns_at(&self, tick: u32, player: usize) -> Vec<Turn> {
+        self.turns.iter()
+            .filter(|(turn_tick, turn_player, _)| *turn_tick == tick && *turn_player == player)
+            .map(|(_, _, turn)| *turn)
+            .collect()
+    }
+
+    // the turns of every player, as GameState::step_players takes them
+    pub fn all_turns_at(&self, tick: u32) -> Vec<Vec<Turn>> {
+        (0..self.config.snakes()).map(|player| self.turns_at(tick, player)).collect()
+    }
+
+    pub fn last_tick(&self) -> u32 {
+        self.turns.last().map_or(0, |(tick, _, _)| *tick)
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty());
+        if !lines.next().is_some_and(|header| header == REPLAY_HEADER || OLD_HEADERS.contains(&header)) {
+            return Err(format!("missing '{}' header", REPLAY_HEADER));
+        }
+        let seed = match lines.next().and_then(|line| line.strip_prefix("seed ")) {
+            Some(seed) => seed.parse().map_err(|_| format!("invalid seed: {}", seed))?,
+            None => return Err("missing seed".to_string())
+        };
+        let mut replay = Replay::new(&GameConfig::default(), seed);
+        for line in lines {
+            let (key, value) = line.split_once(' ').ok_or(format!("invalid line: {}", line))?;
+            if REPLAY_OPTIONS.contains(&key) {
+                replay.config.set(key, value)?;
+                continue;
+            }
+            let (tick, turn) = (key, value);
+            let tick: u32 = tick.parse().map_err(|_| format!("invalid tick: {}", tick))?;
+            let (player, turn) = match turn.split_once(':') {
+                Some((player, turn)) => match player.parse() {
+                    Ok(player) if player < replay.config.snakes() => (player, turn),
+                    _ => return Err(format!("invalid player: {}", player))
+                },
+                None => (0, turn)
+            };
+            let turn: Turn = turn.parse()?;
+            if tick < replay.last_tick() {
+                return Err(format!("turns are not in tick order at tick {}", tick));
+            }
+            replay.turns.push((tick, player, turn));
+        }
+        Ok(replay)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let text = fs::read_to_string(path)?;
+        Replay::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_string())
+    }
+}
+
+impl std::fmt::Display for Replay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", REPLAY_HEADER)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "radius {}", self.config.radius)?;
+        writeln!(f, "holes_num {}", self.config.holes_num)?;
+        writeln!(f, "holes_interval {}", self.config.holes_interval)?;
+        writeln!(f, "tick_rate {}", self.config.tick_rate)?;
+        writeln!(f, "speed_curve {}", self.config.speed_curve)?;
+        writeln!(f, "speed_by {}", self.config.speed_by)?;
+        writeln!(f, "island {}", self.config.island)?;
+        writeln!(f, "players {}", self.config.players)?;
+        writeln!(f, "rivals {}", self.config.rivals)?;
+        if let Some(level) = &self.config.level {
+            writeln!(f, "level {}", level.display())?;
+        }
+        for (tick, player, turn) in &self.turns {
+            write!(f, "{} ", tick)?;
+            if *player > 0 {
+                write!(f, "{}:", player)?;
+            }
+            writeln!(f, "{}", turn)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{IslandShape, SpeedCurve};
+    use crate::direction::Direction;
+    use crate::game::GameState;
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let speed_curve = SpeedCurve::Linear { per_point: 0.5, max_rate: 4 };
+        let level = Some(std::path::PathBuf::from("levels/some lake.txt"));
+        let config = GameConfig { radius: 6, holes_num: 2, speed_curve, level, island: IslandShape::Generated, players: 2, ..GameConfig::default() };
+        let mut replay = Replay::new(&config, 7);
+        replay.record(3, 0, &[Turn::Left, Turn::Left]);
+        replay.record(3, 1, &[Turn::Right]);
+        replay.record(10, 0, &[Turn::Right]);
+        replay.record(12, 1, &[Turn::Towards(Direction::SouthEast)]);
+        assert_eq!(Replay::parse(&replay.to_string()), Ok(replay.clone()));
+        assert_eq!(replay.turns_at(3, 0), vec![Turn::Left, Turn::Left]);
+        assert_eq!(replay.all_turns_at(3), vec![vec![Turn::Left, Turn::Left], vec![Turn::Right]]);
+        assert_eq!(replay.turns_at(4, 0), vec![]);
+        assert!(replay.to_string().contains("12 1:SouthEast"));
+        assert!(replay.to_string().starts_with("snek-replay 2\n"));
+
+        assert_eq!(Replay::parse("snek-replay 2\nseed 7\n").unwrap().config, GameConfig::default());
+        assert!(Replay::parse("seed 7\n").is_err());
+        assert!(Replay::parse("snek-replay 3\nseed 7\n").is_err());
+        // the ones from before there was more than one snake still play
+        let old = Replay::parse("snek-replay 1\nseed 7\nradius 6\n3 L\n5 East\n").unwrap();
+        assert_eq!((old.config.radius, old.all_turns_at(5)), (6, vec![vec![Turn::Towards(Direction::East)]]));
+        assert!(Replay::parse("snek-replay 2\nseed 7\nradius 1\n").is_err());
+        assert!(Replay::parse("snek-replay 2\nseed 7\n3 X\n").is_err());
+        assert!(Replay::parse("snek-replay 2\nseed 7\n3 L\n1 R\n").is_err());
+        assert!(Replay::parse("snek-replay 2\nseed 7\n3 1:L\n").is_err());
+    }
+
+    #[test]
+    fn test_replay_reproduces_game() {
+        let config = GameConfig::default();
+        let mut game = GameState::new(&config, 99);
+        let mut replay = Replay::new(&config, game.get_seed());
+        let moves = [vec![], vec![Turn::Left], vec![], vec![Turn::Right, Turn::Right], vec![]];
+        for turns in moves.iter().cycle().take(40) {
+            replay.record(game.get_tick(), 0, turns);
+            game.step(turns);
+        }
+
+        let mut replayed = GameState::new(&replay.config, replay.seed);
+        while replayed.get_tick() < game.get_tick() {
+            replayed.step_players(&replay.all_turns_at(replayed.get_tick()));
+        }
+        assert_eq!(replayed.get_snake().get_head(), game.get_snake().get_head());
+        assert_eq!(replayed.get_apple(), game.get_apple());
+        assert_eq!(replayed.get_score(), game.get_score());
+        assert_eq!(replayed.is_over(), game.is_over());
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::config::Difficulty;
+use crate::game::GameState;
+use crate::replay::Replay;
+
+// bumped whenever what's saved changes, older saves are turned down rather than misread
+pub const SAVE_VERSION: u32 = 1;
+
+// a game in the middle of being played, with everything needed to go on with it: the whole state and
+// the replay so far, so the replay saved at the end is still the whole game
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame {
+    pub version: u32,
+    pub game: GameState,
+    pub replay: Replay,
+    // the speed picked on the title screen, the game's config has it applied already
+    pub difficulty: Option<Difficulty>,
+    pub play_time_secs: u64,
+}
+
+// only the version, read first so a save from another version gets a clear error
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+impl SavedGame {
+    pub fn new(game: GameState, replay: Replay, difficulty: Option<Difficulty>, play_time_secs: u64) -> SavedGame {
+        SavedGame { version: SAVE_VERSION, game, replay, difficulty, play_time_secs }
+    }
+
+    // the one quick-save slot
+    pub fn quick_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("snek").join("quicksave.json"))
+    }
+
+    // where the game left open when the window closed goes, to be offered on the next start
+    pub fn auto_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("snek").join("autosave.json"))
+    }
+
+    pub fn parse(text: &str) -> Result<SavedGame, String> {
+        let Version { version } = serde_json::from_str(text).map_err(|err| format!("not a saved game: {}", err))?;
+        if version != SAVE_VERSION {
+            return Err(format!("saved by version {} of the format, this is version {}", version, SAVE_VERSION));
+        }
+        let saved: SavedGame = serde_json::from_str(text).map_err(|err| format!("invalid saved game: {}", err))?;
+        saved.game.validate().map_err(|err| format!("invalid saved game: {}", err))?;
+        Ok(saved)
+    }
+
+    pub fn load(path: &Path) -> io::Result<SavedGame> {
+        let text = fs::read_to_string(path)?;
+        SavedGame::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::ai::{Autopilot, Controller};
+    use crate::protocol::Snapshot;
+
+    #[test]
+    fn test_save_and_resume() {
+        let config = GameConfig { holes_num: 4, holes_interval: 1, ..GameConfig::default() };
+        let mut autopilot = Autopilot::new();
+        let (mut game, mut saved) = (GameState::new(&config, 21), GameState::new(&config, 21));
+        for _ in 0..20 {
+            let turns = autopilot.next_turns(&game, 0);
+            game.step(&turns);
+            saved.step(&turns);
+        }
+
+        let path = std::env::temp_dir().join(format!("snek-save-{}.json", std::process::id()));
+        SavedGame::new(saved, Replay::new(&config, 21), Some(Difficulty::Hard), 9).save(&path).unwrap();
+        let loaded = SavedGame::load(&path).unwrap();
+        assert_eq!((loaded.difficulty, loaded.play_time_secs), (Some(Difficulty::Hard), 9));
+        let mut resumed = loaded.game;
+        assert_eq!(Snapshot::of(&resumed), Snapshot::of(&game));
+
+        // the same apples and holes come after loading as would have without it
+        let score = game.get_score();
+        while !game.is_over() && game.get_tick() < 200 {
+            let turns = autopilot.next_turns(&game, 0);
+            game.step(&turns);
+            resumed.step(&turns);
+            assert_eq!(Snapshot::of(&resumed), Snapshot::of(&game));
+        }
+        assert!(game.get_score() > score + 1);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(SavedGame::parse("{\"version\": 0}"), Err(err) if err.starts_with("saved by version 0")));
+        assert!(SavedGame::parse("{\"version\": 1}").is_err());
+        assert!(SavedGame::parse("snek-replay 1").is_err());
+
+        // a state that reads fine but doesn't hang together is turned down too
+        let text = serde_json::to_string(&SavedGame::new(GameState::new(&config, 21), Replay::new(&config, 21), None, 0)).unwrap();
+        assert!(SavedGame::parse(&text).is_ok());
+        assert!(text.contains("\"radius\":9"));
+        let small = text.replace("\"radius\":9", "\"radius\":2");
+        assert!(matches!(SavedGame::parse(&small), Err(err) if err.starts_with("invalid saved game")));
+        // a living snake with nothing left of it has no head to step
+        let mut value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        value["game"]["players"][0]["snake"]["tail"] = serde_json::json!([]);
+        assert!(matches!(SavedGame::parse(&value.to_string()), Err(err) if err.contains("alive without a body")));
+    }
+}
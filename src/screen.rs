@@ -0,0 +1,53 @@
+use crate::bindings::Action;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Screen {
+    Title,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScreenChange {
+    To(Screen),
+    NewGame,
+    Quit,
+}
+
+impl Screen {
+    pub fn on_action(self, action: Action) -> Option<ScreenChange> {
+        match (self, action) {
+            (Screen::Title, Action::Start) => Some(ScreenChange::NewGame),
+            (Screen::Title, Action::Back) => Some(ScreenChange::Quit),
+            (Screen::Playing, Action::Pause) => Some(ScreenChange::To(Screen::Paused)),
+            (Screen::Paused, Action::Pause | Action::Start) => Some(ScreenChange::To(Screen::Playing)),
+            (Screen::Paused, Action::Title) => Some(ScreenChange::To(Screen::Title)),
+            (Screen::GameOver, Action::Restart) => Some(ScreenChange::NewGame),
+            (Screen::GameOver, Action::Back) => Some(ScreenChange::To(Screen::Title)),
+            _ => None
+        }
+    }
+
+    // whether the game keeps ticking on this screen; the game over screen still lets the snake fall
+    pub fn is_running(self) -> bool {
+        matches!(self, Screen::Playing | Screen::GameOver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_action() {
+        assert_eq!(Screen::Title.on_action(Action::Start), Some(ScreenChange::NewGame));
+        assert_eq!(Screen::Title.on_action(Action::Back), Some(ScreenChange::Quit));
+        assert_eq!(Screen::Playing.on_action(Action::Pause), Some(ScreenChange::To(Screen::Paused)));
+        assert_eq!(Screen::Playing.on_action(Action::TurnLeft), None);
+        assert_eq!(Screen::Paused.on_action(Action::Pause), Some(ScreenChange::To(Screen::Playing)));
+        assert_eq!(Screen::Paused.on_action(Action::Title), Some(ScreenChange::To(Screen::Title)));
+        assert_eq!(Screen::GameOver.on_action(Action::Restart), Some(ScreenChange::NewGame));
+        assert_eq!(Screen::GameOver.on_action(Action::Start), None);
+    }
+}
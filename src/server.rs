@@ -0,0 +1,241 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::ai;
+use crate::broadcast::{self, Broadcast};
+use crate::config::GameConfig;
+use crate::game::GameState;
+use crate::input::InputQueue;
+use crate::protocol::{self, ClientMessage, Connection, Event, PROTOCOL_VERSION, ServerMessage, Snapshot};
+
+// every so many ticks the clients get the whole state to check theirs against
+pub const SNAPSHOT_INTERVAL: u32 = 20;
+
+// Runs games for the players connecting to it. The server's game is the real one: the clients send their turns
+// and get back the turns every snake made on every tick, which they step their own copy of the game with.
+// Spectators can connect at any time and get the same as the players. Who comes and goes is sent to the events.
+pub struct Server {
+    local_addr: SocketAddr,
+    // the players that joined, in the order they did, waiting for the next game
+    joins: Receiver<(String, Connection)>,
+    broadcast: Broadcast,
+    config: GameConfig,
+    // None to tick at the game's own speed
+    tick_length: Option<Duration>,
+}
+
+struct Client {
+    connection: Connection,
+    connected: bool,
+}
+
+impl Server {
+    pub fn bind(addr: impl ToSocketAddrs, config: &GameConfig, events: Sender<Event>) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let broadcast = Broadcast::new(events);
+        let (players, joins) = mpsc::channel();
+        let spectators = broadcast.clone();
+        thread::spawn(move || broadcast::accept(listener, Some(players), spectators));
+        Ok(Server { local_addr, joins, broadcast, config: config.clone(), tick_length: None })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    pub fn get_broadcast(&self) -> &Broadcast {
+        &self.broadcast
+    }
+
+    pub fn set_tick_length(&mut self, tick_length: Duration) {
+        self.tick_length = Some(tick_length);
+    }
+
+    // waits for the configured number of players, plays a game with them and returns how it ended
+    pub fn play(&self, seed: u64) -> io::Result<Snapshot> {
+        let level = self.config.load_level(seed)?;
+        let level_text = protocol::level_text(&self.config)?;
+        let (mut clients, names) = self.accept_players()?;
+        let config = GameConfig { level: None, ..self.config.clone() };
+        for (player, client) in clients.iter_mut().enumerate() {
+            client.connection.send(&ServerMessage::Welcome {
+                version: PROTOCOL_VERSION,
+                player: Some(player),
+                names: names.clone(),
+                seed,
+                config: config.clone(),
+                level: level_text.clone(),
+            })?;
+        }
+        self.broadcast.start(seed, &config, level_text, names.clone());
+        let messages = protocol::read_messages(clients.iter().map(|client| &client.connection))?;
+
+        let mut game = GameState::from_level(&self.config, &level, seed);
+        let mut inputs = vec![InputQueue::new(); self.config.players];
+        let mut controllers = ai::controllers(&self.config, 0);
+        while !game.is_over() {
+            let tick_length = self.tick_length.unwrap_or_else(|| Duration::from_secs_f32(1.0 / game.get_tick_rate() as f32));
+            let deadline = Instant::now() + tick_length;
+            loop {
+                let message = match messages.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(message) => message,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "every player left"));
+                    }
+                };
+                match message {
+                    (player, Some(ClientMessage::Input { turns })) => {
+                        for turn in turns {
+                            inputs[player].push(turn);
+                        }
+                    }
+                    (player, Some(ClientMessage::Join { .. } | ClientMessage::Watch { .. })) => {
+                        let error = ServerMessage::Error { message: "already joined".to_string() };
+                        send_all(&mut clients[player..player + 1], &error);
+                    }
+                    (player, None) => {
+                        clients[player].connected = false;
+                        self.broadcast.event(Event::Left { name: names[player].clone() });
+                    }
+                }
+            }
+
+            let turns = ai::next_turns(&mut controllers, &mut inputs, &game);
+            let tick = game.get_tick();
+            game.step_players(&turns);
+            self.send(&mut clients, &ServerMessage::Tick { tick, turns });
+            if game.get_tick().is_multiple_of(SNAPSHOT_INTERVAL) {
+                self.send(&mut clients, &ServerMessage::Snapshot(Snapshot::of(&game)));
+            }
+        }
+
+        let snapshot = Snapshot::of(&game);
+        self.send(&mut clients, &ServerMessage::Snapshot(snapshot.clone()));
+        self.send(&mut clients, &ServerMessage::GameOver { winner: game.get_winner(), scores: game.get_scores() });
+        Ok(snapshot)
+    }
+
+    // to the players and the spectators
+    fn send(&self, clients: &mut [Client], message: &ServerMessage) {
+        send_all(clients, message);
+        self.broadcast.send(message);
+    }
+
+    fn accept_players(&self) -> io::Result<(Vec<Client>, Vec<String>)> {
+        let mut clients = Vec::new();
+        let mut names = Vec::new();
+        while clients.len() < self.config.players {
+            let (name, connection) = self.joins.recv().map_err(|_| io::Error::other("the server stopped listening"))?;
+            self.broadcast.event(Event::Joined { name: name.clone(), player: clients.len() });
+            clients.push(Client { connection, connected: true });
+            names.push(name);
+        }
+        Ok((clients, names))
+    }
+}
+
+// a client that can't be written to has left, its snake goes on without it
+fn send_all(clients: &mut [Client], message: &ServerMessage) {
+    for client in clients.iter_mut().filter(|client| client.connected) {
+        if client.connection.send(message).is_err() {
+            client.connected = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direction::Turn;
+    use crate::protocol::Connection;
+    use std::net::TcpStream;
+
+    fn join(addr: SocketAddr, version: u32, name: &str) -> Connection {
+        let mut connection = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        connection.send(&ClientMessage::Join { version, name: name.to_string() }).unwrap();
+        connection
+    }
+
+    #[test]
+    fn test_play() {
+        let config = GameConfig { radius: 6, players: 2, ..GameConfig::default() };
+        let (events, received) = mpsc::channel();
+        let mut server = Server::bind("127.0.0.1:0", &config, events).unwrap();
+        server.set_tick_length(Duration::from_millis(10));
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || server.play(5).unwrap());
+
+        // one client circles while the other one turns away and goes straight off the island, both follow the game
+        // as it's played; the script gives the turns to send for the tick to come
+        let scripted = |name: &'static str, script: fn(u32) -> Vec<Turn>| thread::spawn(move || {
+            let mut connection = join(addr, PROTOCOL_VERSION, name);
+            let mut game = None;
+            let mut snapshots = Vec::new();
+            loop {
+                match connection.receive::<ServerMessage>().unwrap().unwrap() {
+                    ServerMessage::Welcome { seed, config, level, names, .. } => {
+                        assert!(level.is_none());
+                        assert_eq!(names.len(), 2);
+                        game = Some(GameState::new(&config, seed));
+                        connection.send(&ClientMessage::Input { turns: script(0) }).unwrap();
+                    }
+                    ServerMessage::Tick { tick, turns } => {
+                        let game = game.as_mut().unwrap();
+                        assert_eq!(game.get_tick(), tick);
+                        game.step_players(&turns);
+                        connection.send(&ClientMessage::Input { turns: script(tick + 1) }).unwrap();
+                    }
+                    ServerMessage::Snapshot(snapshot) => {
+                        assert_eq!(Snapshot::of(game.as_ref().unwrap()), snapshot);
+                        snapshots.push(snapshot);
+                    }
+                    ServerMessage::GameOver { scores, .. } => {
+                        assert_eq!(game.unwrap().get_scores(), scores);
+                        return snapshots.pop().unwrap();
+                    }
+                    ServerMessage::Error { message } => panic!("{}", message),
+                }
+            }
+        });
+        let watcher = thread::spawn(move || {
+            let mut connection = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+            connection.send(&ClientMessage::Watch { version: PROTOCOL_VERSION, name: "fan".to_string() }).unwrap();
+            let mut game = None;
+            loop {
+                match connection.receive::<ServerMessage>().unwrap().unwrap() {
+                    ServerMessage::Welcome { player, seed, config, .. } => {
+                        assert_eq!(player, None);
+                        game = Some(GameState::new(&config, seed));
+                    }
+                    ServerMessage::GameOver { .. } => return Snapshot::of(&game.unwrap()),
+                    message => crate::client::apply(game.as_mut().unwrap(), &message).unwrap(),
+                }
+            }
+        });
+        let first = scripted("first", |_| vec![Turn::Left]);
+        // the first one in is player one, the second only connects once it is
+        while !matches!(received.recv().unwrap(), Event::Joined { player: 0, .. }) {}
+        let second = scripted("second", |tick| if tick == 0 { vec![Turn::Right] } else { Vec::new() });
+        let last = handle.join().unwrap();
+        assert_eq!(first.join().unwrap(), last);
+        assert_eq!(second.join().unwrap(), last);
+        assert_eq!(watcher.join().unwrap(), last);
+        assert_eq!(last.alive, vec![true, false]);
+    }
+
+    #[test]
+    fn test_wrong_version() {
+        let server = Server::bind("127.0.0.1:0", &GameConfig::default(), mpsc::channel().0).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.play(0));
+        let mut connection = join(addr, PROTOCOL_VERSION + 1, "future");
+        match connection.receive::<ServerMessage>().unwrap() {
+            Some(ServerMessage::Error { message }) => assert!(message.contains("version")),
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::io;
+use serde::{Serialize, Serializer};
+use crate::ai::{self, Bot, Controller};
+use crate::board;
+use crate::config::GameConfig;
+use crate::game::{Death, GameState};
+
+// how the first player's game ended, written as the death's name, "won" or "timeout"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Died(Death),
+    // the last one standing against the rivals
+    Won,
+    // still going when it was stopped at max_ticks
+    Timeout,
+}
+
+impl Outcome {
+    pub fn of(game: &GameState) -> Outcome {
+        match game.get_death(0) {
+            Some(death) => Outcome::Died(death),
+            None if game.is_over() => Outcome::Won,
+            None => Outcome::Timeout
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Died(death) => death.name(),
+            Outcome::Won => "won",
+            Outcome::Timeout => "timeout",
+        }
+    }
+}
+
+impl Serialize for Outcome {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+// how the first player's game went
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GameReport {
+    pub seed: u64,
+    pub score: i32,
+    pub ticks: u32,
+    pub land_left: usize,
+    pub outcome: Outcome,
+}
+
+impl GameReport {
+    pub const CSV_HEADER: &'static str = "seed,score,ticks,land_left,outcome";
+
+    pub fn csv_row(&self) -> String {
+        format!("{},{},{},{},{}", self.seed, self.score, self.ticks, self.land_left, self.outcome.name())
+    }
+}
+
+// plays a game with every player steered by the bot and the rivals as configured
+pub fn simulate(config: &GameConfig, seed: u64, bot: Bot, max_ticks: u32) -> io::Result<GameReport> {
+    let level = config.load_level(seed)?;
+    let mut game = GameState::from_level(config, &level, seed);
+    let mut controllers: Vec<Box<dyn Controller>> = (0..config.snakes())
+        .map(|snake| if snake < config.players { bot.controller() } else { Bot::Rival(config.rival_difficulty).controller() })
+        .collect();
+    ai::play_out(&mut game, &mut controllers, max_ticks);
+    Ok(GameReport {
+        seed,
+        score: game.get_score(),
+        ticks: game.get_tick(),
+        land_left: board::land_count(game.get_board()),
+        outcome: Outcome::of(&game),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub games: usize,
+    pub average_score: f32,
+    pub min_score: i32,
+    pub quartile_score: i32,
+    pub median_score: i32,
+    pub three_quartile_score: i32,
+    pub max_score: i32,
+    pub average_ticks: f32,
+    pub falls: usize,
+    pub self_bites: usize,
+    pub collisions: usize,
+    pub wins: usize,
+    pub timeouts: usize,
+}
+
+impl Summary {
+    pub const CSV_HEADER: &'static str = "games,average_score,min_score,quartile_score,median_score,three_quartile_score,max_score,\
+        average_ticks,falls,self_bites,collisions,wins,timeouts";
+
+    pub fn new(reports: &[GameReport]) -> Summary {
+        let mut scores: Vec<i32> = reports.iter().map(|report| report.score).collect();
+        scores.sort_unstable();
+        // the nearest rank, the lowest score for no games at all
+        let percentile = |percent: usize| scores.get((scores.len() * percent / 100).min(scores.len().saturating_sub(1))).copied().unwrap_or(0);
+        let average = |total: f32| if reports.is_empty() { 0.0 } else { total / reports.len() as f32 };
+        let outcomes = |outcome: Outcome| reports.iter().filter(|report| report.outcome == outcome).count();
+        Summary {
+            games: reports.len(),
+            average_score: average(scores.iter().sum::<i32>() as f32),
+            min_score: percentile(0),
+            quartile_score: percentile(25),
+            median_score: percentile(50),
+            three_quartile_score: percentile(75),
+            max_score: percentile(100),
+            average_ticks: average(reports.iter().map(|report| report.ticks as f32).sum()),
+            falls: outcomes(Outcome::Died(Death::Fall)),
+            self_bites: outcomes(Outcome::Died(Death::SelfBite)),
+            collisions: outcomes(Outcome::Died(Death::Collision)),
+            wins: outcomes(Outcome::Won),
+            timeouts: outcomes(Outcome::Timeout),
+        }
+    }
+
+    pub fn csv_row(&self) -> String {
+        format!("{},{:.2},{},{},{},{},{},{:.2},{},{},{},{},{}", self.games, self.average_score, self.min_score, self.quartile_score,
+                self.median_score, self.three_quartile_score, self.max_score, self.average_ticks, self.falls, self.self_bites,
+                self.collisions, self.wins, self.timeouts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Difficulty;
+
+    fn report(score: i32, outcome: Outcome) -> GameReport {
+        GameReport { seed: 0, score, ticks: 10 * score as u32, land_left: 100, outcome }
+    }
+
+    #[test]
+    fn test_summary() {
+        let reports = vec![report(4, Outcome::Died(Death::Fall)), report(1, Outcome::Died(Death::SelfBite)), report(9, Outcome::Timeout),
+                           report(6, Outcome::Died(Death::Fall)), report(5, Outcome::Won)];
+        let summary = Summary::new(&reports);
+        assert_eq!(summary.games, 5);
+        assert_eq!(summary.average_score, 5.0);
+        assert_eq!((summary.min_score, summary.median_score, summary.max_score), (1, 5, 9));
+        assert_eq!(summary.average_ticks, 50.0);
+        assert_eq!((summary.falls, summary.self_bites, summary.collisions, summary.wins, summary.timeouts), (2, 1, 0, 1, 1));
+        assert_eq!(summary.csv_row().split(',').count(), Summary::CSV_HEADER.split(',').count());
+        assert_eq!(Summary::new(&[]).max_score, 0);
+    }
+
+    #[test]
+    fn test_simulate() {
+        let config = GameConfig { radius: 6, ..GameConfig::default() };
+        let first = simulate(&config, 3, Bot::Rival(Difficulty::Easy), 500).unwrap();
+        assert_eq!(simulate(&config, 3, Bot::Rival(Difficulty::Easy), 500).unwrap(), first);
+        assert!(first.ticks <= 500);
+        assert_eq!(first.outcome == Outcome::Timeout, first.ticks == 500);
+        assert_eq!(first.csv_row().split(',').count(), GameReport::CSV_HEADER.split(',').count());
+        assert_eq!("hard".parse(), Ok(Bot::Rival(Difficulty::Hard)));
+        assert_eq!("autopilot".parse::<Bot>().unwrap().to_string(), "autopilot");
+        assert!("genius".parse::<Bot>().is_err());
+    }
+
+    #[test]
+    fn test_outcomes_with_a_rival() {
+        // the hard bot outlasts an easy rival now and then, and those games are wins, not timeouts
+        let config = GameConfig { radius: 7, rivals: 1, rival_difficulty: Difficulty::Easy, ..GameConfig::default() };
+        let reports: Vec<GameReport> = (0..6).map(|seed| simulate(&config, seed, Bot::Rival(Difficulty::Hard), 1000).unwrap()).collect();
+        for report in &reports {
+            assert_eq!(report.outcome == Outcome::Timeout, report.ticks == 1000);
+        }
+        let summary = Summary::new(&reports);
+        assert!(summary.wins > 0);
+        assert_eq!(summary.falls + summary.self_bites + summary.collisions + summary.wins + summary.timeouts, 6);
+        assert_eq!(serde_json::to_string(&Outcome::Won).unwrap(), "\"won\"");
+    }
+}
@@ -1,17 +1,15 @@
 use std::collections::VecDeque;
-use ggez::{Context, GameResult, graphics};
-use ggez::event::KeyCode;
-use ggez::graphics::{Color, Drawable, DrawMode, DrawParam};
-use ggez::mint::Point2;
-use crate::direction::Direction;
+use serde::{Deserialize, Serialize};
+use crate::direction::{Direction, Turn};
 use crate::hexagon::HexCoordinates;
-use crate::HEX_SIDE;
 
-#[derive(PartialEq)]
-struct BodyPart{
-    coordinates : HexCoordinates,
-    dir_from : Direction,
-    dir_to : Direction
+pub const START_LENGTH: usize = 3;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct BodyPart{
+    pub coordinates : HexCoordinates,
+    pub dir_from : Direction,
+    pub dir_to : Direction
 }
 
 
@@ -22,18 +20,32 @@ impl BodyPart{
 }
 
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Snake{
     tail : VecDeque<BodyPart>,
     pub falling: bool
 }
 
+impl Default for Snake{
+    fn default() -> Snake{
+        Snake::new()
+    }
+}
+
 impl Snake{
     pub fn new() -> Snake{
-        let head = BodyPart::new(HexCoordinates::new(0, 0, 0), Direction::East, Direction::West);
+        Snake::spawn(HexCoordinates::new(0, 0, 0), Direction::West, START_LENGTH)
+    }
+
+    // a straight snake with its head on the given tile, looking in the given direction
+    pub fn spawn(head: HexCoordinates, dir: Direction, length: usize) -> Snake{
         let mut tail = VecDeque::new();
-        tail.push_back(head);
-        tail.push_back(BodyPart::new(HexCoordinates::new(1 , -1, 0), Direction::East, Direction::West));
-        tail.push_back(BodyPart::new(HexCoordinates::new(2 , -2, 0), Direction::East,Direction::West));
+        let mut coordinates = head;
+        for _ in 0..length{
+            let next = coordinates.move_in_dir(dir.opposite_direction());
+            tail.push_back(BodyPart::new(coordinates, dir.opposite_direction(), dir));
+            coordinates = next;
+        }
         Snake{tail, falling: false}
     }
 
@@ -54,16 +66,16 @@ impl Snake{
         }
     }
 
-    pub fn rotate_head(&mut self, key : KeyCode){
-        let new_dir = Direction::change_dir(self.tail.front().unwrap().dir_to, key);
-        match new_dir {
-            None => {}
-            Some(dir) => {
-                let old_head = self.tail.pop_front().unwrap();
-                let new_head = BodyPart::new(old_head.coordinates,old_head.dir_from, dir);
-                self.tail.push_front(new_head);
-            }
+    // turning back into the neck is ignored
+    pub fn rotate_head(&mut self, turn : Turn){
+        let head = self.tail.front().unwrap();
+        if turn == Turn::Towards(head.dir_from){
+            return;
         }
+        let new_dir = Direction::change_dir(head.dir_to, turn);
+        let old_head = self.tail.pop_front().unwrap();
+        let new_head = BodyPart::new(old_head.coordinates,old_head.dir_from, new_dir);
+        self.tail.push_front(new_head);
     }
 
     pub fn grow(&mut self, coord: HexCoordinates){
@@ -87,11 +99,28 @@ impl Snake{
         &head.coordinates
     }
 
+    // where the head is looking
+    pub fn get_dir(&self) -> Direction{
+        self.tail.front().unwrap().dir_to
+    }
+
     pub fn get_end(&self) -> &HexCoordinates{
         let end = self.tail.back().unwrap();
         &end.coordinates
     }
 
+    pub fn body(&self) -> impl Iterator<Item = &BodyPart>{
+        self.tail.iter()
+    }
+
+    pub fn len(&self) -> usize{
+        self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.tail.is_empty()
+    }
+
     pub fn check_collision(&self, coord: &HexCoordinates) -> bool{
         for body_part in &self.tail{
             if &body_part.coordinates == coord {
@@ -100,55 +129,44 @@ impl Snake{
         }
         false
     }
+}
 
-    pub fn draw(&self, ctx: &mut Context) -> GameResult<()>{
-        //draw head
-        if !self.tail.is_empty() {
-            let mut start_range = 0;
-            if !self.falling {
-                start_range = 1;
-                let head = self.tail.front().unwrap();
-                let (head_center_x, head_center_y) = head.coordinates.get_eucl_center(HEX_SIDE);
-                let (point_from_1, point_from_2) = head.dir_from.get_vertices(HEX_SIDE);
-                let (point_to_1, point_to_2) = head.dir_to.get_vertices(HEX_SIDE);
-
-                let head_mesh = graphics::Mesh::new_polygon(ctx, DrawMode::fill(), &[
-                    point_from_1,
-                    Point2::from([(point_to_1.x + point_to_2.x) / 2_f32, (point_to_1.y + point_to_2.y) / 2_f32]),
-                    point_from_2
-                ], Color::from_rgb(13, 133, 31))?;
-
-
-                head_mesh.draw(ctx, DrawParam::default().dest(Point2::from([head_center_x, head_center_y])))?;
-            }
-
-
-            //draw tail
-            for tail_part in self.tail.range(start_range..) {
-                let (center_x, center_y) = tail_part.coordinates.get_eucl_center(HEX_SIDE);
-                let (point_from_1, point_from_2) = tail_part.dir_from.get_vertices(HEX_SIDE);
-                let (point_to_1, point_to_2) = tail_part.dir_to.get_vertices(HEX_SIDE);
-                let tail_part_mesh = graphics::Mesh::new_polygon(ctx, DrawMode::fill(), &[
-                    point_from_1,
-                    point_from_2,
-                    point_to_1,
-                    point_to_2
-                ], Color::from_rgb(13, 133, 31))?;
-                tail_part_mesh.draw(ctx, DrawParam::default().dest(Point2::from([center_x, center_y])))?;
-            }
-        }
-
-        Ok(())
+// the rotation that points a snake at from looking dir closest to target, None if it should go straight on;
+// the angles are measured on the screen, so any point can be aimed at, not only the six directions
+pub fn turn_towards(from: &HexCoordinates, dir: Direction, target: &HexCoordinates) -> Option<Turn>{
+    if from == target{
+        return None;
     }
-
-
-
+    let angle = |to: &HexCoordinates| {
+        let (from_x, from_y) = from.get_eucl_center(1.0);
+        let (to_x, to_y) = to.get_eucl_center(1.0);
+        (to_y - from_y).atan2(to_x - from_x)
+    };
+    let target_angle = angle(target);
+    let off_by = |turn: Option<Turn>| {
+        let new_dir = turn.map_or(dir, |turn| Direction::change_dir(dir, turn));
+        let diff = (angle(&from.move_in_dir(new_dir)) - target_angle).abs();
+        diff.min(2.0 * std::f32::consts::PI - diff)
+    };
+    let options = [None, Some(Turn::Left), Some(Turn::Right)];
+    options.iter().copied().min_by(|a, b| off_by(*a).total_cmp(&off_by(*b))).unwrap()
 }
 
 #[cfg(test)]
 mod tests{
     use super::*;
 
+    #[test]
+    fn test_spawn(){
+        let snek = Snake::new();
+        let coordinates: Vec<&HexCoordinates> = snek.body().map(|part| &part.coordinates).collect();
+        assert_eq!(coordinates, vec![&HexCoordinates::new(0, 0, 0), &HexCoordinates::new(1, -1, 0), &HexCoordinates::new(2, -2, 0)]);
+        assert!(snek.body().all(|part| part.dir_from == Direction::East && part.dir_to == Direction::West));
+
+        let snek = Snake::spawn(HexCoordinates::new(1, 1, -2), Direction::NorthEast, 2);
+        assert_eq!(snek.get_end(), &HexCoordinates::new(1, 2, -3));
+    }
+
     #[test]
     fn test_move(){
         let snek1 = Snake::new();
@@ -166,21 +184,23 @@ mod tests{
     fn test_rotate_head(){
         let mut snek = Snake::new();
 
-        snek.rotate_head(KeyCode::Right);
+        snek.rotate_head(Turn::Right);
         assert_eq!(snek.tail.front().unwrap().dir_to, Direction::NorthWest);
 
-        snek.rotate_head(KeyCode::A);
+        snek.rotate_head(Turn::Left);
         assert_eq!(snek.tail.front().unwrap().dir_to, Direction::West);
 
-        snek.rotate_head(KeyCode::Left);
-        assert_eq!(snek.tail.front().unwrap().dir_to, Direction::SouthWest);
-
-        snek.rotate_head(KeyCode::L);
+        snek.rotate_head(Turn::Left);
         assert_eq!(snek.tail.front().unwrap().dir_to, Direction::SouthWest);
 
-        snek.rotate_head(KeyCode::Right);
+        snek.rotate_head(Turn::Right);
         assert_eq!(snek.tail.front().unwrap().dir_to, Direction::West);
 
+        snek.rotate_head(Turn::Towards(Direction::NorthEast));
+        assert_eq!(snek.tail.front().unwrap().dir_to, Direction::NorthEast);
+
+        snek.rotate_head(Turn::Towards(Direction::East));
+        assert_eq!(snek.tail.front().unwrap().dir_to, Direction::NorthEast);
     }
 
     #[test]
@@ -208,6 +228,19 @@ mod tests{
 
     }
 
+    #[test]
+    fn test_turn_towards(){
+        let head = HexCoordinates::new(0, 0, 0);
+        assert_eq!(turn_towards(&head, Direction::West, &HexCoordinates::new(-4, 4, 0)), None);
+        assert_eq!(turn_towards(&head, Direction::West, &HexCoordinates::new(-3, 0, 3)), Some(Turn::Right));
+        assert_eq!(turn_towards(&head, Direction::West, &HexCoordinates::new(0, 3, -3)), Some(Turn::Left));
+        // straight up from a snake going East is closer to NorthEast than to East
+        assert_eq!(turn_towards(&head, Direction::East, &HexCoordinates::new(-2, -2, 4)), Some(Turn::Left));
+        // behind the snake only a turn gets it closer
+        assert!(turn_towards(&head, Direction::West, &HexCoordinates::new(3, -3, 0)).is_some());
+        assert_eq!(turn_towards(&head, Direction::West, &head), None);
+    }
+
     #[test]
     fn test_collision(){
         let snek = Snake::new();